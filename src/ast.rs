@@ -0,0 +1,274 @@
+use std::cmp;
+use num_bigint::BigInt;
+use lexer::{Symbol, Token};
+use error::ParserError;
+use function::Definition;
+
+// Characters of the input from start up to, but not including, end
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    // The characters a token takes up when it starts at position
+    pub fn of(position: usize, token: &Token) -> Span {
+        Span::new(position, position + width(token))
+    }
+
+    // Smallest span covering both
+    pub fn to(self, other: Span) -> Span {
+        Span::new(cmp::min(self.start, other.start), cmp::max(self.end, other.end))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Node {
+    Literal(BigInt),
+    Variable(String),
+    LastResult,
+    Slot(usize),
+    Operation(Symbol, Vec<Expr>),
+    Call(String, Vec<Expr>),
+    Assignment(String, Box<Expr>),
+}
+
+// A node along with the span of the token it came from, which errors point
+// at, and the span of the whole sub-expression
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expr {
+    pub node: Node,
+    pub token: Span,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    Empty,
+    Command(String, Span, Option<Expr>), // Keyword with its optional literal argument
+    History(Span, String),               // Search the REPL's history, an empty term lists it all
+    Definition(String, Span, Definition),
+    Expression(Expr),
+}
+
+// Deepest a tree may nest. Walking it, and even dropping it, recurses once
+// per level, so anything deeper would overflow the stack.
+pub const MAX_DEPTH: usize = 256;
+
+pub fn arity(symbol: &Symbol) -> usize {
+    match *symbol {
+        Symbol::NOT | Symbol::NEG | Symbol::LNOT => 1,
+        Symbol::SLICE => 3,
+        _ => 2,
+    }
+}
+
+// Characters the token takes up in the input. Index and slice operators are
+// placed at their opening square bracket.
+fn width(token: &Token) -> usize {
+    match *token {
+        Token::Call(ref name, _) | Token::Assignment(ref name) => name.chars().count(),
+        Token::Operator(Symbol::INDEX) | Token::Operator(Symbol::SLICE) => 1,
+        _ => token.to_string().chars().count(),
+    }
+}
+
+// Every operand after the first, which has nothing to combine with
+fn extra(operands: &[Expr]) -> Span {
+    operands[2..].iter().fold(operands[1].span, |span, operand| span.to(operand.span))
+}
+
+// Fold postfix tokens into a tree, each operator taking the operands before it
+pub fn expression(tokens: &[(usize, Token)]) -> Result<Expr, ParserError> {
+    let mut operands: Vec<Expr> = Vec::new();
+    let mut depths: Vec<usize> = Vec::new(); // How deeply each operand nests
+
+    for &(position, ref token) in tokens {
+        let count = match *token {
+            Token::Operator(ref symbol) => arity(symbol),
+            Token::Call(_, count) => count,
+            Token::Assignment(_) => 1,
+            _ => 0,
+        };
+        if operands.len() < count {
+            return Err(ParserError::MissingArgument(Span::of(position, token)));
+        }
+        // An assignment stores the whole expression, so nothing may be left over
+        if let Token::Assignment(_) = *token {
+            if operands.len() > 1 {
+                return Err(ParserError::TooManyArguments(extra(&operands)));
+            }
+        }
+        let mut args = operands.split_off(operands.len() - count);
+        let token_span = Span::of(position, token);
+        let depth = 1 + depths.split_off(depths.len() - count).into_iter().max().unwrap_or(0);
+        if depth > MAX_DEPTH {
+            return Err(ParserError::TooDeep(token_span));
+        }
+        let span = args.iter().fold(token_span, |span, arg| span.to(arg.span));
+
+        let node = match *token {
+            Token::Decimal(_) | Token::Hexadecimal(_) | Token::Octal(_) | Token::Binary(_) => {
+                Node::Literal(token.value().unwrap())
+            }
+            Token::Identifier(ref name) => Node::Variable(name.clone()),
+            Token::LastResult => Node::LastResult,
+            Token::Slot(slot) => Node::Slot(slot),
+            Token::Operator(ref symbol) => Node::Operation(symbol.clone(), args),
+            Token::Call(ref name, _) => Node::Call(name.clone(), args),
+            Token::Assignment(ref name) => Node::Assignment(name.clone(), Box::new(args.pop().unwrap())),
+            _ => unreachable!(),
+        };
+        operands.push(Expr {
+            node,
+            token: token_span,
+            span,
+        });
+        depths.push(depth);
+    }
+
+    match operands.len() {
+        1 => Ok(operands.pop().unwrap()),
+        0 => Err(ParserError::MissingArgument(Span::new(0, 0))),
+        _ => Err(ParserError::TooManyArguments(extra(&operands))),
+    }
+}
+
+// A statement from the postfix tokens of a whole line
+pub fn statement(tokens: &[(usize, Token)]) -> Result<Statement, ParserError> {
+    match *tokens {
+        [] => Ok(Statement::Empty),
+        [(position, Token::Keyword(ref keyword)), (_, Token::Term(ref term))] => {
+            let span = Span::new(position, position + keyword.chars().count());
+            Ok(Statement::History(span, term.clone()))
+        }
+        [(position, Token::Keyword(ref keyword)), ref argument @ ..] => {
+            let span = Span::new(position, position + keyword.chars().count());
+            let argument = if argument.is_empty() {
+                None
+            } else {
+                Some(expression(argument)?)
+            };
+            Ok(Statement::Command(keyword.clone(), span, argument))
+        }
+        _ => Ok(Statement::Expression(expression(tokens)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use ast::{expression, statement, Expr, Node, Span, Statement, MAX_DEPTH};
+    use error::ParserError;
+    use lexer::{Symbol, Token, Tokens};
+
+    fn literal(value: u32, start: usize, end: usize) -> Expr {
+        Expr {
+            node: Node::Literal(BigInt::from(value)),
+            token: Span::new(start, end),
+            span: Span::new(start, end),
+        }
+    }
+
+    #[test]
+    fn operation_spans() {
+        // 12 | 0x1 << 3
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("12".to_string())),
+            (5, Token::Hexadecimal("0x1".to_string())),
+            (12, Token::Decimal("3".to_string())),
+            (9, Token::Operator(Symbol::LSHIFT)),
+            (3, Token::Operator(Symbol::OR)),
+        ];
+        let shift = Expr {
+            node: Node::Operation(Symbol::LSHIFT, vec![literal(1, 5, 8), literal(3, 12, 13)]),
+            token: Span::new(9, 11),
+            span: Span::new(5, 13),
+        };
+        let or = Expr {
+            node: Node::Operation(Symbol::OR, vec![literal(12, 0, 2), shift]),
+            token: Span::new(3, 4),
+            span: Span::new(0, 13),
+        };
+        assert_eq!(expression(&tokens), Ok(or));
+    }
+
+    #[test]
+    fn call_and_assignment_spans() {
+        // a = popcount(0b101)
+        let tokens: Tokens = vec![
+            (13, Token::Binary("0b101".to_string())),
+            (4, Token::Call("popcount".to_string(), 1)),
+            (0, Token::Assignment("a".to_string())),
+        ];
+        let call = Expr {
+            node: Node::Call("popcount".to_string(), vec![literal(5, 13, 18)]),
+            token: Span::new(4, 12),
+            span: Span::new(4, 18),
+        };
+        let assignment = Expr {
+            node: Node::Assignment("a".to_string(), Box::new(call)),
+            token: Span::new(0, 1),
+            span: Span::new(0, 18),
+        };
+        assert_eq!(statement(&tokens), Ok(Statement::Expression(assignment)));
+    }
+
+    #[test]
+    fn command() {
+        let tokens: Tokens = vec![
+            (0, Token::Keyword("width".to_string())),
+            (6, Token::Decimal("64".to_string())),
+        ];
+        assert_eq!(
+            statement(&tokens),
+            Ok(Statement::Command(
+                "width".to_string(),
+                Span::new(0, 5),
+                Some(literal(64, 6, 8))
+            ))
+        );
+        assert_eq!(statement(&[]), Ok(Statement::Empty));
+
+        let tokens: Tokens = vec![
+            (0, Token::Keyword("history".to_string())),
+            (8, Token::Term("1 +".to_string())),
+        ];
+        assert_eq!(statement(&tokens), Ok(Statement::History(Span::new(0, 7), "1 +".to_string())));
+    }
+
+    #[test]
+    fn operand_count() {
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::AND)),
+        ];
+        assert_eq!(expression(&tokens), Err(ParserError::MissingArgument(Span::new(2, 3))));
+
+        let tokens: Tokens = vec![(0, Token::Operator(Symbol::AND))];
+        assert_eq!(expression(&tokens), Err(ParserError::MissingArgument(Span::new(0, 1))));
+
+        // 1 12 << 0xf
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (6, Token::Decimal("12".to_string())),
+            (11, Token::Hexadecimal("0xf".to_string())),
+            (8, Token::Operator(Symbol::LSHIFT)),
+        ];
+        assert_eq!(expression(&tokens), Err(ParserError::TooManyArguments(Span::new(6, 14))));
+    }
+
+    #[test]
+    fn depth_limit() {
+        // ~~...~1, with one operator more than fits
+        let mut tokens: Tokens = vec![(MAX_DEPTH, Token::Decimal("1".to_string()))];
+        tokens.extend((0..MAX_DEPTH).rev().map(|i| (i, Token::Operator(Symbol::NOT))));
+        assert!(expression(&tokens[..MAX_DEPTH]).is_ok());
+        assert_eq!(expression(&tokens), Err(ParserError::TooDeep(Span::new(0, 1))));
+    }
+}
@@ -26,11 +26,101 @@ pub const HELP: &str = "Welcome to bspl!
 
 bspl is a REPL for practising bitwise operations.
 
-bspl does unsigned 32-bit integer operations
-using either decimal or hexadecimal operands,
-and the following bitwise operators: ~ | & ^ << >>
+bspl does integer operations, unsigned 32-bit by default
+('width', 'signed' and 'big' below change this),
+using decimal (42), hexadecimal (0x2a), octal (0o52)
+or binary (0b10_1010) operands,
+the bitwise operators ~ | & ^ << >>
+the rotate operators <<< >>>
+the arithmetic operators + - * / % **
+and the comparison and logical operators
+== != < <= > >= && || ! which produce 1 or 0
+
+Builtin functions are called as name(arg, ...):
+popcount clz ctz parity bswap bitrev log2 ispow2 nextpow2
+BIT(n) GENMASK(h, l) FIELD_GET(mask, v) FIELD_PREP(mask, v)
+setbit testbit clearbit togglebit (v, n)
+extract(v, hi, lo) insert(v, field, hi, lo)
+
+Bits are sliced with x[hi:lo] or picked with x[n]
+
+Values are kept in variables with mask = 0xff << 8
+and used in later expressions like mask & 0xf0f0.
+A command typed on its own line, like 'help',
+runs the command even if a variable shares its name.
+
+Every result is numbered, _ is the last result
+and $3 is the third one.
+Variables and results that no longer fit after the
+width or mode changes are truncated, as the trace shows.
+
+Functions are defined with
+def align_up(x, a) = (x + a - 1) & ~(a - 1)
+and listed with 'defs'. They may call other
+functions, but not themselves.
+
+Type 'history' to list earlier lines,
+or 'history mask' to search for them.
+
+Press Tab to complete commands, functions and variables.
+Input continues on the next line while brackets are
+open or when the line ends with a backslash.
+
+Type 'signed' to switch to two's complement arithmetic,
+where - negates its operand and >> shifts arithmetically.
+Type 'unsigned' to switch back.
+
+Type 'big' for arbitrary-precision integers that never
+overflow, where ~x is -x-1 just like in Python.
+Results are limited to 16777216 bits there.
+Type 'width 32' to return to a fixed width.
+
+Type 'width' to see the current word width,
+or 'width 64' to switch to 8, 16, 32, 64 or 128 bits.
+
+Arithmetic that does not fit the current width
+is reported as an overflow instead of wrapping.
 
 Try out the following expression to get started.
 12 | (1 << 12)";
 
-pub const KEYWORDS: &[&str] = &["license", "exit", "help", "version"];
+pub const KEYWORDS: &[&str] = &[
+    "license", "exit", "help", "version", "width", "signed", "unsigned", "big", "defs", "history",
+];
+
+// Words typed at the start of a line that are handled by the REPL itself
+pub const COMMANDS: &[&str] = &["def"];
+
+// Signatures and summaries shown while completing
+pub const SIGNATURES: &[(&str, &str, &str)] = &[
+    ("license", "license", "show the license"),
+    ("exit", "exit", "leave bspl"),
+    ("help", "help", "show the help"),
+    ("version", "version", "show the version"),
+    ("width", "width [8|16|32|64|128]", "show or set the width"),
+    ("signed", "signed", "use two's complement values"),
+    ("unsigned", "unsigned", "use unsigned values"),
+    ("big", "big", "use arbitrary precision"),
+    ("defs", "defs", "list defined functions"),
+    ("history", "history [TERM]", "list or search history"),
+    ("def", "def name(params) = expr", "define a function"),
+    ("popcount", "popcount(v)", "number of set bits"),
+    ("clz", "clz(v)", "leading zero bits"),
+    ("ctz", "ctz(v)", "trailing zero bits"),
+    ("parity", "parity(v)", "1 if an odd number of bits is set"),
+    ("bswap", "bswap(v)", "reverse the bytes"),
+    ("bitrev", "bitrev(v)", "reverse the bits"),
+    ("log2", "log2(v)", "index of the highest set bit"),
+    ("ispow2", "ispow2(v)", "1 if v is a power of two"),
+    ("nextpow2", "nextpow2(v)", "smallest power of two >= v"),
+    ("BIT", "BIT(n)", "only bit n set"),
+    ("GENMASK", "GENMASK(h, l)", "bits h down to l set"),
+    ("FIELD_GET", "FIELD_GET(mask, v)", "field of v under mask"),
+    ("FIELD_PREP", "FIELD_PREP(mask, v)", "v shifted into mask"),
+    ("setbit", "setbit(v, n)", "v with bit n set"),
+    ("clearbit", "clearbit(v, n)", "v with bit n cleared"),
+    ("togglebit", "togglebit(v, n)", "v with bit n flipped"),
+    ("testbit", "testbit(v, n)", "1 if bit n of v is set"),
+    ("extract", "extract(v, hi, lo)", "bits hi down to lo of v"),
+    ("insert", "insert(v, field, hi, lo)", "v with field in bits hi to lo"),
+];
@@ -0,0 +1,107 @@
+use std::iter;
+use unicode_width::UnicodeWidthChar;
+use ast::Span;
+
+// An error over a span of the input, with notes pointing at related places
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+    pub notes: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(span: Span, message: S) -> Diagnostic {
+        Diagnostic {
+            span,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn note(mut self, span: Span, message: &str) -> Diagnostic {
+        self.notes.push((span, message.to_string()));
+        self
+    }
+
+    // The error followed by each of its notes
+    pub fn labels(&self) -> Vec<(Span, &str)> {
+        iter::once((self.span, self.message.as_str()))
+            .chain(self.notes.iter().map(|&(span, ref note)| (span, note.as_str())))
+            .collect()
+    }
+}
+
+// The line and column of a character in input that may span several lines
+pub fn locate(input: &str, position: usize) -> (usize, usize) {
+    let lines: Vec<&str> = input.split('\n').collect();
+    let mut column = position;
+    let mut line = 0;
+    while line + 1 < lines.len() && column > lines[line].chars().count() {
+        column -= lines[line].chars().count() + 1;
+        line += 1;
+    }
+    (line, column)
+}
+
+fn width(character: char) -> usize {
+    character.width().unwrap_or(0)
+}
+
+// The line a span starts on, along with carets to print beneath it. Columns
+// follow the display width of each character and tabs are kept as they are,
+// so the carets line up under wide or combining characters too. A span
+// past the end of the line, or an empty one, still gets a single caret.
+pub fn underline(input: &str, span: Span) -> (usize, &str, String) {
+    let (line, column) = locate(input, span.start);
+    let text = input.split('\n').nth(line).unwrap_or("");
+
+    let mut underline = String::new();
+    for character in text.chars().take(column) {
+        match character {
+            '\t' => underline.push('\t'),
+            _ => underline.extend((0..width(character)).map(|_| ' ')),
+        }
+    }
+    let length = span.end.saturating_sub(span.start);
+    let carets: usize = text.chars().skip(column).take(length).map(width).sum();
+    underline.extend((0..carets.max(1)).map(|_| '^'));
+    (line, text, underline)
+}
+
+#[cfg(test)]
+mod tests {
+    use ast::Span;
+    use diagnostic::{locate, underline};
+
+    #[test]
+    fn underline_span() {
+        let input = "12 + 0xfg";
+        assert_eq!(underline(input, Span::new(5, 9)), (0, input, "     ^^^^".to_string()));
+        assert_eq!(underline(input, Span::new(3, 3)), (0, input, "   ^".to_string()));
+    }
+
+    #[test]
+    fn display_width() {
+        // Each of these characters takes up two columns
+        let input = "中文 + x";
+        assert_eq!(underline(input, Span::new(5, 6)), (0, input, "       ^".to_string()));
+        assert_eq!(underline(input, Span::new(0, 2)), (0, input, "^^^^".to_string()));
+
+        // A combining accent takes up none
+        let input = "e\u{301} + y";
+        assert_eq!(underline(input, Span::new(5, 6)), (0, input, "    ^".to_string()));
+
+        let input = "1 \t+ y";
+        assert_eq!(underline(input, Span::new(5, 6)), (0, input, "  \t  ^".to_string()));
+    }
+
+    #[test]
+    fn several_lines() {
+        let input = "(1 +\n2";
+        assert_eq!(locate(input, 4), (0, 4));
+        assert_eq!(locate(input, 5), (1, 0));
+        // Just past the end of the input
+        assert_eq!(underline(input, Span::new(6, 7)), (1, "2", " ^".to_string()));
+    }
+}
@@ -1,21 +1,81 @@
+use ast::Span;
+use mode::Width;
+
 #[derive(Debug, PartialEq)]
 pub enum LexerError {
-    UnknownOperator(usize),
-    RadixError(usize),
+    UnknownOperator(Span),
+    RadixError(Span),
+    DecimalError(Span),
+    HexadecimalError(Span),
+    OctalError(Span),
+    BinaryError(Span),
+    SlotError(Span),
+    OutOfRange(Span, Width),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ParserError {
-    MissingOpeningBracket(usize),
-    MissingClosingBracket(usize),
-    KeywordError(usize),
+    MissingOpeningBracket(Span),
+    MissingClosingBracket(Span),
+    KeywordError(Span),
+    MisplacedComma(Span),
+    MisplacedColon(Span),
+    MisplacedSlice(Span),
+    EmptySlice(Span),
+    MisplacedAssignment(Span),
+    DefinitionError(Span),
+    MissingArgument(Span),
+    TooManyArguments(Span),
+    TooDeep(Span),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum EvaluatorError {
-    MissingArgument(usize),
-    TooManyArguments,
-    OverflowShift(usize),
-    UnknownKeyword(usize),
+    MissingArgument(Span),
+    TooManyArguments(Span),
+    OverflowShift(Span),
+    NegativeShift(Span),
+    DivisionByZero(Span),
+    Overflow(Span),
+    TooLarge(Span),
+    NegativeExponent(Span),
+    FixedWidthRequired(Span),
+    UnknownFunction(Span),
+    UnknownVariable(Span),
+    UnknownSlot(Span),
+    Recursion(Span),
+    ReservedName(Span),
+    Domain(Span),
+    BitOutOfRange(Span),
+    ReversedBounds(Span),
+    UnknownKeyword(Span),
+    InvalidArgument(Span),
+    PromptOnly(Span),
+    TooDeep(Span),
     Exit,
 }
+
+impl EvaluatorError {
+    // The same error reported at another position, so that errors inside a
+    // user-defined function point at its call instead of its definition
+    pub fn moved_to(self, span: Span) -> EvaluatorError {
+        match self {
+            EvaluatorError::MissingArgument(_) => EvaluatorError::MissingArgument(span),
+            EvaluatorError::TooManyArguments(_) => EvaluatorError::TooManyArguments(span),
+            EvaluatorError::OverflowShift(_) => EvaluatorError::OverflowShift(span),
+            EvaluatorError::NegativeShift(_) => EvaluatorError::NegativeShift(span),
+            EvaluatorError::DivisionByZero(_) => EvaluatorError::DivisionByZero(span),
+            EvaluatorError::Overflow(_) => EvaluatorError::Overflow(span),
+            EvaluatorError::TooLarge(_) => EvaluatorError::TooLarge(span),
+            EvaluatorError::NegativeExponent(_) => EvaluatorError::NegativeExponent(span),
+            EvaluatorError::FixedWidthRequired(_) => EvaluatorError::FixedWidthRequired(span),
+            EvaluatorError::UnknownFunction(_) => EvaluatorError::UnknownFunction(span),
+            EvaluatorError::UnknownVariable(_) => EvaluatorError::UnknownVariable(span),
+            EvaluatorError::UnknownSlot(_) => EvaluatorError::UnknownSlot(span),
+            EvaluatorError::Domain(_) => EvaluatorError::Domain(span),
+            EvaluatorError::BitOutOfRange(_) => EvaluatorError::BitOutOfRange(span),
+            EvaluatorError::ReversedBounds(_) => EvaluatorError::ReversedBounds(span),
+            error => error,
+        }
+    }
+}
@@ -1,91 +1,524 @@
-use lexer::{Symbol, Token, Tokens};
-use function::{functions, Function, Functions};
+use std::cmp;
+use std::collections::HashMap;
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+use ast::{Expr, Node, Span, Statement, MAX_DEPTH};
+use lexer::Symbol;
+use function::{functions, Body, Definition, Function, Functions};
 use error::EvaluatorError;
 use constants::{HELP, KEYWORDS, LICENSE, VERSION};
-use std::u32;
+use mode::{Mode, Width};
+use program::{Instruction, Op, Program};
 
 pub struct Evaluator {
     functions: Functions,
+    variables: HashMap<String, BigInt>,
+    // Final value of every expression, `$1` being the first
+    slots: Vec<BigInt>,
+    mode: Mode,
 }
 
-fn is_keyword(variable: &String) -> Option<&str> {
-    for keyword in KEYWORDS {
-        if keyword.to_string() == variable.to_lowercase() {
-            return Some(keyword);
-        }
-    }
-
-    None
+fn is_keyword(variable: &str) -> Option<&'static str> {
+    let variable = variable.to_lowercase();
+    KEYWORDS.iter().find(|&&keyword| keyword == variable).cloned()
 }
 
 impl Evaluator {
     pub fn new() -> Evaluator {
         Evaluator {
             functions: Functions::new(),
+            variables: HashMap::new(),
+            slots: Vec::new(),
+            mode: Mode::default(),
         }
     }
 
-    pub fn evaluate(&self, tokens: Tokens) -> Result<Vec<String>, EvaluatorError> {
-        let mut result: Vec<String> = Vec::new();
-        let mut stack: Vec<u32> = Vec::new();
-
-        if tokens.is_empty() {
-            return Ok(result);
+    // A copy of the session with the same mode, variables, results and
+    // definitions, which builtins are registered into afresh
+    pub fn snapshot(&self) -> Evaluator {
+        let mut evaluator = Evaluator {
+            variables: self.variables.clone(),
+            slots: self.slots.clone(),
+            mode: self.mode,
+            ..Evaluator::default()
+        };
+        for (symbol, function) in &self.functions {
+            if let Body::Defined(ref definition) = function.body {
+                let function = Function::defined(definition.clone(), function.depth);
+                evaluator.functions.insert(symbol.clone(), function);
+            }
         }
+        evaluator
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    // Number of results recorded so far, which is also the slot of the latest
+    pub fn slots(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn set_width(&mut self, width: Width) {
+        self.mode.width = width;
+    }
+
+    pub fn set_signed(&mut self, signed: bool) {
+        self.mode.signed = signed;
+    }
 
-        for (position, token) in tokens {
-            match token {
-                Token::Decimal(ref dec) => {
-                    stack.push(dec.parse().unwrap());
+    fn command(
+        &mut self,
+        keyword: &str,
+        argument: Option<&Expr>,
+    ) -> Result<Vec<String>, EvaluatorError> {
+        let mut result: Vec<String> = Vec::new();
+
+        match (keyword, argument) {
+            ("version", None) => result.push(VERSION.to_string()),
+            ("help", None) => {
+                result = HELP.lines().map(|line| line.to_string()).collect();
+            }
+            ("license", None) => {
+                result = LICENSE.lines().map(|line| line.to_string()).collect();
+            }
+            ("exit", None) => return Err(EvaluatorError::Exit),
+            ("defs", None) => {
+                result = self
+                    .functions()
+                    .into_iter()
+                    .filter_map(|(_, definition)| definition)
+                    .collect();
+                if result.is_empty() {
+                    result.push("No functions defined".to_string());
                 }
-                Token::Hexadecimal(ref hex) => {
-                    stack.push(u32::from_str_radix(hex.as_str().split_at(2).1, 16).unwrap());
+            }
+            ("width", None) => result.push(format!("Width is {}", self.mode.width)),
+            ("width", Some(argument)) => {
+                match argument.node {
+                    Node::Literal(ref bits) if Width::from_bits(bits).is_some() => {
+                        self.mode.width = Width::from_bits(bits).unwrap();
+                    }
+                    _ => return Err(EvaluatorError::InvalidArgument(argument.span)),
                 }
-                Token::Keyword(ref kw) => {
-                    if let Some(keyword) = is_keyword(kw) {
-                        match keyword {
-                            "version" => result.push(VERSION.to_string()),
-                            "help" => {
-                                result = HELP.lines().map(|line| line.to_string()).collect();
-                            }
-                            "license" => {
-                                result = LICENSE.lines().map(|line| line.to_string()).collect();
-                            }
-                            "exit" => return Err(EvaluatorError::Exit),
-                            _ => unreachable!(),
+                result.push(format!("Width set to {}", self.mode.width));
+            }
+            ("big", None) => {
+                self.mode.width = Width::Unbounded;
+                result.push(format!("Mode set to {}", self.mode));
+            }
+            ("signed", None) => {
+                self.mode.signed = true;
+                result.push(format!("Mode set to {}", self.mode));
+            }
+            ("unsigned", None) => {
+                self.mode.signed = false;
+                result.push(format!("Mode set to {}", self.mode));
+            }
+            (_, Some(argument)) => return Err(EvaluatorError::InvalidArgument(argument.span)),
+            _ => unreachable!(),
+        }
+
+        Ok(result)
+    }
+
+    // Store a user-defined function, as long as it cannot end up calling itself
+    fn define(
+        &mut self,
+        span: Span,
+        name: &str,
+        definition: &Definition,
+    ) -> Result<Vec<String>, EvaluatorError> {
+        let symbol = Symbol::Function(name.to_string());
+        if let Some(&Function { body: Body::Builtin(_), .. }) = self.functions.get(&symbol) {
+            return Err(EvaluatorError::ReservedName(span));
+        }
+        // A call nests one level deeper than its body, so that must still fit
+        let depth = self.nesting(&definition.body)?;
+        if depth == MAX_DEPTH {
+            return Err(EvaluatorError::TooDeep(definition.body.token));
+        }
+        if self.reaches(&definition.body, name) {
+            return Err(EvaluatorError::Recursion(span));
+        }
+
+        self.functions.insert(symbol, Function::defined(definition.clone(), depth));
+        Ok(vec![format!("Defined {}({})", name, definition.params.join(", "))])
+    }
+
+    // Whether the calls in a body lead back to the named function. Every stored
+    // definition already passed this check, so following them always terminates.
+    fn reaches(&self, body: &Expr, name: &str) -> bool {
+        match body.node {
+            Node::Call(ref callee, ref args) => {
+                callee == name
+                    || match self.functions.get(&Symbol::Function(callee.clone())) {
+                        Some(&Function { body: Body::Defined(ref definition), .. }) => {
+                            self.reaches(&definition.body, name)
                         }
-                        return Ok(result);
-                    } else {
-                        return Err(EvaluatorError::UnknownKeyword(position));
+                        _ => false,
                     }
+                    || args.iter().any(|arg| self.reaches(arg, name))
+            }
+            Node::Operation(_, ref args) => args.iter().any(|arg| self.reaches(arg, name)),
+            Node::Assignment(_, ref value) => self.reaches(value, name),
+            _ => false,
+        }
+    }
+
+    // Every callable function, with the text of those defined in the REPL
+    pub fn functions(&self) -> Vec<(String, Option<String>)> {
+        let mut functions: Vec<(String, Option<String>)> = self
+            .functions
+            .iter()
+            .filter_map(|(symbol, function)| match (symbol, &function.body) {
+                (Symbol::Function(name), Body::Defined(definition)) => Some((
+                    name.clone(),
+                    Some(format!(
+                        "{}({}) = {}",
+                        name,
+                        definition.params.join(", "),
+                        definition.text
+                    )),
+                )),
+                (Symbol::Function(name), Body::Builtin(_)) => Some((name.clone(), None)),
+                _ => None,
+            })
+            .collect();
+        functions.sort();
+        functions
+    }
+
+    // Every variable with its value in the current mode
+    pub fn variables(&self) -> Vec<(String, String)> {
+        let mut variables: Vec<(String, String)> = self
+            .variables
+            .iter()
+            .map(|(name, value)| {
+                let value = self.mode.width.mask(value.clone());
+                (name.clone(), self.mode.decimal(&value))
+            })
+            .collect();
+        variables.sort();
+        variables
+    }
+
+    // Walk an expression depth first, with `locals` shadowing the session
+    // variables. Operands are evaluated left to right before their operator,
+    // so every step is traced in the order it is calculated.
+    fn compute(
+        &self,
+        expr: &Expr,
+        locals: &HashMap<String, BigInt>,
+        result: &mut Vec<String>,
+        assignments: &mut Vec<(String, BigInt)>,
+    ) -> Result<BigInt, EvaluatorError> {
+        let span = expr.token;
+        let args = match expr.node {
+            // Literals in a definition were checked against the width it was made in
+            Node::Literal(ref value) => return Ok(self.mode.width.mask(value.clone())),
+            Node::Variable(ref name) => {
+                if let Some(value) = locals.get(name) {
+                    return Ok(self.mode.width.mask(value.clone()));
                 }
-                Token::Operator(ref op) => {
-                    let function = self.functions.get(&op).unwrap();
-                    if stack.len() < function.arity {
-                        return Err(EvaluatorError::MissingArgument(position));
-                    }
-                    let stack_len = stack.len();
-                    let args: Vec<u32> = stack.split_off(stack_len - function.arity);
-                    let interm_result = try!((function.handle)(args, position));
-                    stack.push(interm_result.0);
-                    result.push(interm_result.1);
-                    result.push(interm_result.0.to_string());
+                return match self.variables.get(name) {
+                    Some(value) => Ok(self.recall(name, value, result)),
+                    None => Err(EvaluatorError::UnknownVariable(span)),
+                };
+            }
+            Node::LastResult | Node::Slot(_) => {
+                let (slot, name) = match expr.node {
+                    Node::Slot(slot) => (slot, format!("${}", slot)),
+                    _ => (self.slots.len(), "_".to_string()),
+                };
+                return match slot.checked_sub(1).and_then(|index| self.slots.get(index)) {
+                    Some(value) => Ok(self.recall(&name, value, result)),
+                    None => Err(EvaluatorError::UnknownSlot(span)),
+                };
+            }
+            Node::Assignment(ref name, ref value) => {
+                let value = self.compute(value, locals, result, assignments)?;
+                result.push(format!("{} = {}", name, self.mode.decimal(&value)));
+                result.push(self.mode.decimal(&value));
+                assignments.push((name.clone(), value.clone()));
+                return Ok(value);
+            }
+            Node::Operation(_, ref args) | Node::Call(_, ref args) => args,
+        };
+
+        let mut values: Vec<BigInt> = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.compute(arg, locals, result, assignments)?);
+            if let Some((value, trace)) = self.short_circuit(&expr.node, &values) {
+                result.push(trace);
+                result.push(self.mode.decimal(&value));
+                return Ok(value);
+            }
+        }
+
+        // Calls only look up their function once the arguments have evaluated
+        let (function, name) = match expr.node {
+            Node::Operation(ref symbol, _) => (self.functions.get(symbol).unwrap(), None),
+            Node::Call(ref name, _) => match self.functions.get(&Symbol::Function(name.clone())) {
+                Some(function) => (function, Some(name)),
+                None => return Err(EvaluatorError::UnknownFunction(span)),
+            },
+            _ => unreachable!(),
+        };
+        takes(function, args, span)?;
+
+        let (value, trace) = match (&function.body, name) {
+            (Body::Builtin(handle), _) => handle(values, span, self.mode)?,
+            (Body::Defined(definition), Some(name)) => {
+                let trace: Vec<String> = values.iter().map(|arg| self.mode.decimal(arg)).collect();
+                let trace = format!("{}({})", name, trace.join(", "));
+                let locals = definition.params.iter().cloned().zip(values).collect();
+                let value = self
+                    .compute(&definition.body, &locals, result, assignments)
+                    .map_err(|error| error.moved_to(span))?;
+                (value, trace)
+            }
+            _ => unreachable!(),
+        };
+        result.push(trace);
+        result.push(self.mode.decimal(&value));
+        Ok(value)
+    }
+
+    // How deeply computing an expression recurses, counting the bodies of the
+    // defined functions it calls. It keeps a stack of its own, so it is safe
+    // to run on any expression before the recursive walks below.
+    fn nesting(&self, expr: &Expr) -> Result<usize, EvaluatorError> {
+        let mut deepest = 0;
+        let mut pending = vec![(expr, 1)];
+        while let Some((expr, level)) = pending.pop() {
+            let depth = level + match expr.node {
+                Node::Call(ref name, _) => self
+                    .functions
+                    .get(&Symbol::Function(name.clone()))
+                    .map_or(0, |function| function.depth),
+                _ => 0,
+            };
+            if depth > MAX_DEPTH {
+                return Err(EvaluatorError::TooDeep(expr.token));
+            }
+            deepest = cmp::max(deepest, depth);
+            match expr.node {
+                Node::Operation(_, ref args) | Node::Call(_, ref args) => {
+                    pending.extend(args.iter().map(|arg| (arg, level + 1)))
+                }
+                Node::Assignment(_, ref value) => pending.push((value, level + 1)),
+                _ => (),
+            }
+        }
+        Ok(deepest)
+    }
+
+    // Find the mistakes that do not depend on the values involved, such as
+    // unknown names and wrong argument counts, without evaluating anything.
+    // Names in `locals` count as bound, as `x` is for `--map`.
+    pub fn check(&self, expr: &Expr, locals: &[&str]) -> Result<(), EvaluatorError> {
+        self.nesting(expr)?;
+        self.bound(expr, locals)
+    }
+
+    fn bound(&self, expr: &Expr, locals: &[&str]) -> Result<(), EvaluatorError> {
+        let span = expr.token;
+        match expr.node {
+            Node::Literal(_) => Ok(()),
+            Node::Variable(ref name) => {
+                if locals.contains(&name.as_str()) || self.variables.contains_key(name) {
+                    Ok(())
+                } else {
+                    Err(EvaluatorError::UnknownVariable(span))
+                }
+            }
+            Node::LastResult | Node::Slot(_) => {
+                let slot = match expr.node {
+                    Node::Slot(slot) => slot,
+                    _ => self.slots.len(),
+                };
+                match slot.checked_sub(1).and_then(|index| self.slots.get(index)) {
+                    Some(_) => Ok(()),
+                    None => Err(EvaluatorError::UnknownSlot(span)),
+                }
+            }
+            Node::Assignment(_, ref value) => self.bound(value, locals),
+            Node::Operation(_, ref args) => args.iter().try_for_each(|arg| self.bound(arg, locals)),
+            Node::Call(ref name, ref args) => {
+                for arg in args {
+                    self.bound(arg, locals)?;
+                }
+                match self.functions.get(&Symbol::Function(name.clone())) {
+                    Some(function) => takes(function, args, span),
+                    None => Err(EvaluatorError::UnknownFunction(span)),
                 }
-                _ => unreachable!(),
             }
         }
+    }
+
+    // Stored values keep the number they were in the mode they were stored
+    // in. One that no longer fits the current mode is truncated to it, and
+    // the truncation is traced.
+    fn recall(&self, name: &str, value: &BigInt, result: &mut Vec<String>) -> BigInt {
+        let truncated = self.mode.width.mask(value.clone());
+        if !self.mode.contains(value) {
+            result.push(format!("{} is {}, truncated to {}", name, value, self.mode));
+            result.push(self.mode.decimal(&truncated));
+        }
+        truncated
+    }
+
+    // && and || skip their right operand once the left one decides the result
+    fn short_circuit(&self, node: &Node, values: &[BigInt]) -> Option<(BigInt, String)> {
+        let (symbol, a) = match (node, values) {
+            (Node::Operation(symbol, _), [a]) => (symbol, a),
+            _ => return None,
+        };
+        let value = match *symbol {
+            Symbol::LAND if a.is_zero() => 0,
+            Symbol::LOR if !a.is_zero() => 1,
+            _ => return None,
+        };
+        Some((BigInt::from(value), format!("{} {} ...", self.mode.decimal(a), symbol)))
+    }
+
+    // Value of an expression without storing anything, and without running
+    // commands or definitions
+    pub fn preview(&self, statement: &Statement) -> Option<BigInt> {
+        match *statement {
+            Statement::Expression(ref expr) => {
+                self.nesting(expr).ok()?;
+                self.compute(expr, &HashMap::new(), &mut Vec::new(), &mut Vec::new()).ok()
+            }
+            _ => None,
+        }
+    }
+
+    // Value of an expression with some names bound to values, as `--map` binds
+    // `x` to each input. Nothing is stored, so it can run for every line.
+    pub fn apply(
+        &self,
+        expr: &Expr,
+        locals: &HashMap<String, BigInt>,
+    ) -> Result<BigInt, EvaluatorError> {
+        self.nesting(expr)?;
+        self.compute(expr, locals, &mut Vec::new(), &mut Vec::new())
+    }
+
+    // Compile an expression into a `Program`, with `input` naming the value it
+    // is run on and variables fixed at their current values. Expressions that
+    // do not compile, such as those calling defined functions or using an
+    // unbounded width, return None and are left to `apply`.
+    pub fn compile(&self, expr: &Expr, input: &str) -> Option<Program> {
+        self.nesting(expr).ok()?;
+        let mut code = Vec::new();
+        self.emit(expr, input, &mut code)?;
+        Program::new(code, self.mode)
+    }
+
+    // Instructions for an expression, after those for its operands
+    fn emit(&self, expr: &Expr, input: &str, code: &mut Vec<Instruction>) -> Option<()> {
+        let instruction = match expr.node {
+            Node::Literal(ref value) => Instruction::Push(self.mode.width.mask(value.clone()).to_u128()?),
+            Node::Variable(ref name) if name == input => Instruction::Input,
+            Node::Variable(ref name) => {
+                Instruction::Push(self.mode.width.mask(self.variables.get(name)?.clone()).to_u128()?)
+            }
+            Node::LastResult | Node::Slot(_) => {
+                let slot = match expr.node {
+                    Node::Slot(slot) => slot,
+                    _ => self.slots.len(),
+                };
+                let value = slot.checked_sub(1).and_then(|index| self.slots.get(index))?;
+                Instruction::Push(self.mode.width.mask(value.clone()).to_u128()?)
+            }
+            Node::Operation(ref symbol, ref args) => {
+                let op = Op::from_symbol(symbol)?;
+                if op == Op::LAnd || op == Op::LOr {
+                    // Jump past the right operand once the left one decides the result
+                    self.emit(&args[0], input, code)?;
+                    let skip = code.len();
+                    code.push(Instruction::Skip(op, 0));
+                    self.emit(&args[1], input, code)?;
+                    code.push(Instruction::Apply(op, expr.token));
+                    code[skip] = Instruction::Skip(op, code.len());
+                    return Some(());
+                }
+                for arg in args {
+                    self.emit(arg, input, code)?;
+                }
+                Instruction::Apply(op, expr.token)
+            }
+            Node::Call(ref name, ref args) => {
+                let symbol = Symbol::Function(name.clone());
+                match self.functions.get(&symbol) {
+                    Some(&Function { arity, body: Body::Builtin(_), .. }) if arity == args.len() => {
+                        for arg in args {
+                            self.emit(arg, input, code)?;
+                        }
+                        Instruction::Apply(Op::from_symbol(&symbol)?, expr.token)
+                    }
+                    _ => return None,
+                }
+            }
+            Node::Assignment(..) => return None,
+        };
+        code.push(instruction);
+        Some(())
+    }
+
+    pub fn evaluate(&mut self, statement: Statement) -> Result<Vec<String>, EvaluatorError> {
+        let mut result: Vec<String> = Vec::new();
+        // Variables are only stored once the whole line has evaluated
+        let mut assignments: Vec<(String, BigInt)> = Vec::new();
+
+        let expr = match statement {
+            Statement::Empty => return Ok(result),
+            Statement::Command(ref keyword, span, ref argument) => {
+                return match is_keyword(keyword) {
+                    Some(keyword) => self.command(keyword, argument.as_ref()),
+                    None => Err(EvaluatorError::UnknownKeyword(span)),
+                };
+            }
+            Statement::Definition(ref name, span, ref definition) => {
+                return self.define(span, name, definition);
+            }
+            // History belongs to the REPL, which answers it before evaluating
+            Statement::History(span, _) => return Err(EvaluatorError::PromptOnly(span)),
+            Statement::Expression(expr) => expr,
+        };
 
-        // The last element on the stack should be the final result
-        if stack.len() != 1 {
-            return Err(EvaluatorError::TooManyArguments);
-        } else if result.is_empty() {
-            result.push(stack.pop().unwrap().to_string());
+        self.nesting(&expr)?;
+        let value = self.compute(&expr, &HashMap::new(), &mut result, &mut assignments)?;
+        if result.is_empty() {
+            result.push(self.mode.decimal(&value));
         }
+        let mode = self.mode;
+        self.variables
+            .extend(assignments.into_iter().map(|(name, value)| (name, mode.value(&value))));
+        self.slots.push(self.mode.value(&value));
 
         Ok(result)
     }
 }
 
+// Whether a function is called with as many arguments as it has parameters
+fn takes(function: &Function, args: &[Expr], span: Span) -> Result<(), EvaluatorError> {
+    if args.len() > function.arity {
+        // The arguments past the last parameter
+        let extra = args[function.arity + 1..]
+            .iter()
+            .fold(args[function.arity].span, |span, arg| span.to(arg.span));
+        return Err(EvaluatorError::TooManyArguments(extra));
+    }
+    if args.len() < function.arity {
+        return Err(EvaluatorError::MissingArgument(span));
+    }
+    Ok(())
+}
+
 impl Default for Evaluator {
     fn default() -> Evaluator {
         let mut evaluator = Evaluator::new();
@@ -94,6 +527,10 @@ impl Default for Evaluator {
             Symbol::NOT,
             Function::new(1, Box::new(functions::not))
         );
+        evaluator.functions.insert(
+            Symbol::NEG,
+            Function::new(1, Box::new(functions::neg))
+        );
         evaluator.functions.insert(
             Symbol::XOR,
             Function::new(2, Box::new(functions::xor))
@@ -114,6 +551,158 @@ impl Default for Evaluator {
             Symbol::RSHIFT,
             Function::new(2, Box::new(functions::rshift))
         );
+        evaluator.functions.insert(
+            Symbol::ROTL,
+            Function::new(2, Box::new(functions::rotl))
+        );
+        evaluator.functions.insert(
+            Symbol::ROTR,
+            Function::new(2, Box::new(functions::rotr))
+        );
+        evaluator.functions.insert(
+            Symbol::ADD,
+            Function::new(2, Box::new(functions::add))
+        );
+        evaluator.functions.insert(
+            Symbol::SUB,
+            Function::new(2, Box::new(functions::sub))
+        );
+        evaluator.functions.insert(
+            Symbol::MUL,
+            Function::new(2, Box::new(functions::mul))
+        );
+        evaluator.functions.insert(
+            Symbol::DIV,
+            Function::new(2, Box::new(functions::div))
+        );
+        evaluator.functions.insert(
+            Symbol::MOD,
+            Function::new(2, Box::new(functions::rem))
+        );
+        evaluator.functions.insert(
+            Symbol::POW,
+            Function::new(2, Box::new(functions::pow))
+        );
+        evaluator.functions.insert(
+            Symbol::EQ,
+            Function::new(2, Box::new(functions::eq))
+        );
+        evaluator.functions.insert(
+            Symbol::NE,
+            Function::new(2, Box::new(functions::ne))
+        );
+        evaluator.functions.insert(
+            Symbol::LT,
+            Function::new(2, Box::new(functions::lt))
+        );
+        evaluator.functions.insert(
+            Symbol::LE,
+            Function::new(2, Box::new(functions::le))
+        );
+        evaluator.functions.insert(
+            Symbol::GT,
+            Function::new(2, Box::new(functions::gt))
+        );
+        evaluator.functions.insert(
+            Symbol::GE,
+            Function::new(2, Box::new(functions::ge))
+        );
+        evaluator.functions.insert(
+            Symbol::LAND,
+            Function::new(2, Box::new(functions::land))
+        );
+        evaluator.functions.insert(
+            Symbol::LOR,
+            Function::new(2, Box::new(functions::lor))
+        );
+        evaluator.functions.insert(
+            Symbol::LNOT,
+            Function::new(1, Box::new(functions::lnot))
+        );
+        evaluator.functions.insert(
+            Symbol::INDEX,
+            Function::new(2, Box::new(functions::index))
+        );
+        evaluator.functions.insert(
+            Symbol::SLICE,
+            Function::new(3, Box::new(functions::slice))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("popcount".to_string()),
+            Function::new(1, Box::new(functions::popcount))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("clz".to_string()),
+            Function::new(1, Box::new(functions::clz))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("ctz".to_string()),
+            Function::new(1, Box::new(functions::ctz))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("parity".to_string()),
+            Function::new(1, Box::new(functions::parity))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("bswap".to_string()),
+            Function::new(1, Box::new(functions::bswap))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("bitrev".to_string()),
+            Function::new(1, Box::new(functions::bitrev))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("log2".to_string()),
+            Function::new(1, Box::new(functions::log2))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("ispow2".to_string()),
+            Function::new(1, Box::new(functions::ispow2))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("nextpow2".to_string()),
+            Function::new(1, Box::new(functions::nextpow2))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("BIT".to_string()),
+            Function::new(1, Box::new(functions::bit))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("GENMASK".to_string()),
+            Function::new(2, Box::new(functions::genmask))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("FIELD_GET".to_string()),
+            Function::new(2, Box::new(functions::field_get))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("FIELD_PREP".to_string()),
+            Function::new(2, Box::new(functions::field_prep))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("setbit".to_string()),
+            Function::new(2, Box::new(functions::setbit))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("clearbit".to_string()),
+            Function::new(2, Box::new(functions::clearbit))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("togglebit".to_string()),
+            Function::new(2, Box::new(functions::togglebit))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("testbit".to_string()),
+            Function::new(2, Box::new(functions::testbit))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("extract".to_string()),
+            Function::new(3, Box::new(functions::extract))
+        );
+        evaluator.functions.insert(
+            Symbol::Function("insert".to_string()),
+            Function::new(4, Box::new(functions::insert))
+        );
 
         evaluator
     }
@@ -121,223 +710,1620 @@ impl Default for Evaluator {
 
 #[cfg(test)]
 mod tests {
+    use num_bigint::BigInt;
     use lexer::{Symbol, Token, Tokens};
     use evaluator::Evaluator;
-    use error::EvaluatorError;
+    use error::{EvaluatorError, ParserError};
     use constants::{HELP, LICENSE};
+    use mode::{Mode, Width};
+    use function::{functions, Definition};
+    use ast::{self, Expr, Span, Statement, MAX_DEPTH};
+
+    // Tests give the postfix tokens a line parses to, which `ast` folds into a tree
+    fn statement(tokens: Tokens) -> Statement {
+        ast::statement(&tokens).unwrap()
+    }
+
+    fn expression(tokens: Tokens) -> Expr {
+        ast::expression(&tokens).unwrap()
+    }
+
+    fn define(position: usize, name: &str, definition: Definition) -> Statement {
+        Statement::Definition(name.to_string(), Span::new(position, position + name.len()), definition)
+    }
 
     #[test]
     fn blank() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![];
         let result: Vec<String> = vec![];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn decimal() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![(0, Token::Decimal("12".to_string()))];
         let result: Vec<String> = vec!["12".to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn hexadecimal() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![(0, Token::Hexadecimal("0xc".to_string()))];
         let result: Vec<String> = vec!["12".to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn keyword_exit() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![(0, Token::Keyword("exit".to_string()))];
-        assert_eq!(evaluator.evaluate(tokens), Err(EvaluatorError::Exit));
+        assert_eq!(evaluator.evaluate(statement(tokens)), Err(EvaluatorError::Exit));
     }
 
     #[test]
     fn keyword_license() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![(0, Token::Keyword("license".to_string()))];
         let result: Vec<String> = LICENSE.lines().map(|line| line.to_string()).collect();
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn keyword_help() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![(0, Token::Keyword("help".to_string()))];
         let result: Vec<String> = HELP.lines().map(|line| line.to_string()).collect();
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn keyword_version() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![(0, Token::Keyword("version".to_string()))];
         let result: Vec<String> = vec![env!("CARGO_PKG_VERSION").to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn keyword_unknown() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![(0, Token::Keyword("rust".to_string()))];
         assert_eq!(
-            evaluator.evaluate(tokens),
-            Err(EvaluatorError::UnknownKeyword(0))
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::UnknownKeyword(Span::new(0, 4)))
         );
     }
 
+    #[test]
+    fn binary_octal() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Binary("0b1010_0000".to_string())),
+            (14, Token::Octal("0o7".to_string())),
+            (12, Token::Operator(Symbol::OR)),
+        ];
+        let result: Vec<String> = vec!["160 | 7".to_string(), "167".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
     #[test]
     fn expression_hexadecimal() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
             (0, Token::Hexadecimal("0x1".to_string())),
             (4, Token::Hexadecimal("0xc".to_string())),
             (2, Token::Operator(Symbol::LSHIFT)),
         ];
         let result: Vec<String> = vec!["1 << 12".to_string(), "4096".to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn expression_decimal_lshift() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
             (0, Token::Decimal("1".to_string())),
             (4, Token::Decimal("12".to_string())),
             (2, Token::Operator(Symbol::LSHIFT)),
         ];
         let result: Vec<String> = vec!["1 << 12".to_string(), "4096".to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn expression_decimal_lshift_overflow() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
             (0, Token::Decimal("4294967295".to_string())),
             (14, Token::Decimal("32".to_string())),
             (11, Token::Operator(Symbol::LSHIFT)),
         ];
         assert_eq!(
-            evaluator.evaluate(tokens),
-            Err(EvaluatorError::OverflowShift(11))
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::OverflowShift(Span::new(11, 13)))
         );
     }
 
     #[test]
     fn expression_decimal_rshift() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
             (0, Token::Decimal("12".to_string())),
             (4, Token::Decimal("1".to_string())),
             (2, Token::Operator(Symbol::RSHIFT)),
         ];
         let result: Vec<String> = vec!["12 >> 1".to_string(), "6".to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn expression_decimal_rshift_overflow() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
             (0, Token::Decimal("1".to_string())),
             (4, Token::Decimal("32".to_string())),
             (2, Token::Operator(Symbol::RSHIFT)),
         ];
         assert_eq!(
-            evaluator.evaluate(tokens),
-            Err(EvaluatorError::OverflowShift(2))
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::OverflowShift(Span::new(2, 4)))
         );
     }
 
     #[test]
     fn expression_decimal_xor() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
             (0, Token::Decimal("1".to_string())),
             (4, Token::Decimal("12".to_string())),
             (2, Token::Operator(Symbol::XOR)),
         ];
         let result: Vec<String> = vec!["1 ^ 12".to_string(), "13".to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn expression_decimal_and() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
             (0, Token::Decimal("1".to_string())),
             (4, Token::Decimal("12".to_string())),
             (2, Token::Operator(Symbol::AND)),
         ];
         let result: Vec<String> = vec!["1 & 12".to_string(), "0".to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn expression_decimal_or() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
             (0, Token::Decimal("32".to_string())),
             (5, Token::Decimal("10".to_string())),
             (3, Token::Operator(Symbol::OR)),
         ];
         let result: Vec<String> = vec!["32 | 10".to_string(), "42".to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
     fn expression_decimal_not() {
-        let evaluator = Evaluator::default();
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
             (1, Token::Decimal("12".to_string())),
             (0, Token::Operator(Symbol::NOT)),
         ];
         let result: Vec<String> = vec!["~12".to_string(), "4294967283".to_string()];
-        assert_eq!(evaluator.evaluate(tokens).unwrap(), result);
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
     }
 
     #[test]
-    fn expression_missing_argument() {
-        let evaluator = Evaluator::default();
+    fn keyword_width() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![(0, Token::Keyword("width".to_string()))];
+        let result: Vec<String> = vec!["Width is 32-bit".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn keyword_width_argument() {
+        let mut evaluator = Evaluator::default();
         let tokens: Tokens = vec![
-            (0, Token::Decimal("1".to_string())),
-            (2, Token::Operator(Symbol::AND)),
+            (0, Token::Keyword("width".to_string())),
+            (6, Token::Decimal("64".to_string())),
+        ];
+        let result: Vec<String> = vec!["Width set to 64-bit".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+        assert_eq!(evaluator.mode().width, Width::W64);
+    }
+
+    #[test]
+    fn keyword_width_invalid() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Keyword("width".to_string())),
+            (6, Token::Decimal("12".to_string())),
         ];
         assert_eq!(
-            evaluator.evaluate(tokens),
-            Err(EvaluatorError::MissingArgument(2))
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::InvalidArgument(Span::new(6, 8)))
         );
+        assert_eq!(evaluator.mode().width, Width::W32);
     }
 
     #[test]
-    fn expression_missing_arguments() {
-        let evaluator = Evaluator::default();
-        let tokens: Tokens = vec![(0, Token::Operator(Symbol::AND))];
+    fn keyword_unexpected_argument() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Keyword("help".to_string())),
+            (5, Token::Decimal("1".to_string())),
+        ];
         assert_eq!(
-            evaluator.evaluate(tokens),
-            Err(EvaluatorError::MissingArgument(0))
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::InvalidArgument(Span::new(5, 6)))
         );
     }
 
     #[test]
-    fn expression_too_many_arguments() {
-        let evaluator = Evaluator::default();
+    fn expression_width_not() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("12".to_string())),
+            (0, Token::Operator(Symbol::NOT)),
+        ];
+        let result: Vec<String> = vec!["~12".to_string(), "243".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_width_lshift() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W64);
         let tokens: Tokens = vec![
             (0, Token::Decimal("1".to_string())),
-            (6, Token::Decimal("12".to_string())),
-            (11, Token::Hexadecimal("0xf".to_string())),
-            (8, Token::Operator(Symbol::LSHIFT)),
+            (5, Token::Decimal("40".to_string())),
+            (2, Token::Operator(Symbol::LSHIFT)),
+        ];
+        let result: Vec<String> = vec!["1 << 40".to_string(), "1099511627776".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_width_lshift_overflow() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W16);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (5, Token::Decimal("16".to_string())),
+            (2, Token::Operator(Symbol::LSHIFT)),
         ];
         assert_eq!(
-            evaluator.evaluate(tokens),
-            Err(EvaluatorError::TooManyArguments)
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::OverflowShift(Span::new(2, 4)))
         );
     }
 
+    #[test]
+    fn keyword_signed() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![(0, Token::Keyword("signed".to_string()))];
+        let result: Vec<String> = vec!["Mode set to signed 32-bit".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+        assert!(evaluator.mode().signed);
+    }
+
+    #[test]
+    fn expression_neg_unsigned() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("1".to_string())),
+            (0, Token::Operator(Symbol::NEG)),
+        ];
+        let result: Vec<String> = vec!["-1".to_string(), "255".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_neg_signed() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        let tokens: Tokens = vec![
+            (2, Token::Decimal("12".to_string())),
+            (1, Token::Operator(Symbol::NEG)),
+            (0, Token::Operator(Symbol::NOT)),
+        ];
+        let result: Vec<String> = vec![
+            "-12".to_string(),
+            "-12".to_string(),
+            "~-12".to_string(),
+            "11".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_rshift_arithmetic() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("100".to_string())),
+            (0, Token::Operator(Symbol::NEG)),
+            (8, Token::Decimal("3".to_string())),
+            (5, Token::Operator(Symbol::RSHIFT)),
+        ];
+        let result: Vec<String> = vec![
+            "-100".to_string(),
+            "-100".to_string(),
+            "-100 >> 3".to_string(),
+            "-13".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_neg_minimum() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (1, Token::Hexadecimal("0x80".to_string())),
+            (0, Token::Operator(Symbol::NEG)),
+        ];
+        let result: Vec<String> = vec!["-(-128)".to_string(), "-128".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_rshift_logical() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("128".to_string())),
+            (7, Token::Decimal("3".to_string())),
+            (4, Token::Operator(Symbol::RSHIFT)),
+        ];
+        let result: Vec<String> = vec!["128 >> 3".to_string(), "16".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn keyword_big() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![(0, Token::Keyword("big".to_string()))];
+        let result: Vec<String> = vec!["Mode set to arbitrary-precision".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+        assert_eq!(evaluator.mode().width, Width::Unbounded);
+    }
+
+    #[test]
+    fn expression_unbounded_lshift() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::Unbounded);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (5, Token::Decimal("100".to_string())),
+            (2, Token::Operator(Symbol::LSHIFT)),
+        ];
+        let result: Vec<String> = vec![
+            "1 << 100".to_string(),
+            "1267650600228229401496703205376".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_unbounded_large() {
+        // Called directly, since printing results this size takes a while
+        let mode = Mode { width: Width::Unbounded, signed: false };
+        let args = vec![BigInt::from(2), BigInt::from(2_000_000)];
+        let (power, _) = functions::pow(args, Span::new(2, 4), mode).unwrap();
+        let args = vec![BigInt::from(1), BigInt::from(2_000_000)];
+        let (shifted, _) = functions::lshift(args, Span::new(2, 4), mode).unwrap();
+        assert_eq!(power.bits(), 2_000_001);
+        assert_eq!(power, shifted);
+    }
+
+    #[test]
+    fn expression_unbounded_too_large() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::Unbounded);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (5, Token::Decimal("16777216".to_string())),
+            (2, Token::Operator(Symbol::LSHIFT)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::TooLarge(Span::new(2, 4)))
+        );
+
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("3".to_string())),
+            (5, Token::Decimal("16777216".to_string())),
+            (2, Token::Operator(Symbol::POW)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::TooLarge(Span::new(2, 4)))
+        );
+    }
+
+    #[test]
+    fn expression_unbounded_rshift_past_limit() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::Unbounded);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("5".to_string())),
+            (0, Token::Operator(Symbol::NEG)),
+            (6, Token::Decimal("100000000".to_string())),
+            (3, Token::Operator(Symbol::RSHIFT)),
+        ];
+        let result = evaluator.evaluate(statement(tokens)).unwrap();
+        assert_eq!(result.last().unwrap(), "-1");
+    }
+
+    #[test]
+    fn expression_unbounded_not() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::Unbounded);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("12".to_string())),
+            (0, Token::Operator(Symbol::NOT)),
+        ];
+        let result: Vec<String> = vec!["~12".to_string(), "-13".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_unbounded_rshift() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::Unbounded);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("5".to_string())),
+            (0, Token::Operator(Symbol::NEG)),
+            (6, Token::Decimal("1".to_string())),
+            (3, Token::Operator(Symbol::RSHIFT)),
+        ];
+        let result: Vec<String> = vec![
+            "-5".to_string(),
+            "-5".to_string(),
+            "-5 >> 1".to_string(),
+            "-3".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_unbounded_negative_shift() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::Unbounded);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (6, Token::Decimal("1".to_string())),
+            (5, Token::Operator(Symbol::NEG)),
+            (2, Token::Operator(Symbol::LSHIFT)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::NegativeShift(Span::new(2, 4)))
+        );
+    }
+
+    #[test]
+    fn expression_add() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("7".to_string())),
+            (4, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::ADD)),
+        ];
+        let result: Vec<String> = vec!["7 + 1".to_string(), "8".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_add_overflow() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("255".to_string())),
+            (6, Token::Decimal("1".to_string())),
+            (4, Token::Operator(Symbol::ADD)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::Overflow(Span::new(4, 5)))
+        );
+    }
+
+    #[test]
+    fn expression_sub_underflow() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("0".to_string())),
+            (4, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::SUB)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::Overflow(Span::new(2, 3)))
+        );
+    }
+
+    #[test]
+    fn expression_sub_signed() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("0".to_string())),
+            (4, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::SUB)),
+        ];
+        let result: Vec<String> = vec!["0 - 1".to_string(), "-1".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_mul() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("6".to_string())),
+            (4, Token::Decimal("7".to_string())),
+            (2, Token::Operator(Symbol::MUL)),
+        ];
+        let result: Vec<String> = vec!["6 * 7".to_string(), "42".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_div_by_zero() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (4, Token::Decimal("0".to_string())),
+            (2, Token::Operator(Symbol::DIV)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::DivisionByZero(Span::new(2, 3)))
+        );
+    }
+
+    #[test]
+    fn expression_div_signed_truncates() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("7".to_string())),
+            (0, Token::Operator(Symbol::NEG)),
+            (5, Token::Decimal("2".to_string())),
+            (3, Token::Operator(Symbol::DIV)),
+        ];
+        let result: Vec<String> = vec![
+            "-7".to_string(),
+            "-7".to_string(),
+            "-7 / 2".to_string(),
+            "-3".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_div_signed_overflow() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (0, Token::Hexadecimal("0x80".to_string())),
+            (8, Token::Hexadecimal("0xff".to_string())),
+            (5, Token::Operator(Symbol::DIV)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::Overflow(Span::new(5, 6)))
+        );
+    }
+
+    #[test]
+    fn expression_mod_unbounded_floors() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::Unbounded);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("7".to_string())),
+            (0, Token::Operator(Symbol::NEG)),
+            (5, Token::Decimal("3".to_string())),
+            (3, Token::Operator(Symbol::MOD)),
+        ];
+        let result: Vec<String> = vec![
+            "-7".to_string(),
+            "-7".to_string(),
+            "-7 % 3".to_string(),
+            "2".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_pow() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("2".to_string())),
+            (5, Token::Decimal("31".to_string())),
+            (2, Token::Operator(Symbol::POW)),
+        ];
+        let result: Vec<String> = vec!["2 ** 31".to_string(), "2147483648".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_pow_overflow() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("2".to_string())),
+            (5, Token::Decimal("32".to_string())),
+            (2, Token::Operator(Symbol::POW)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::Overflow(Span::new(2, 4)))
+        );
+    }
+
+    #[test]
+    fn expression_pow_huge_exponent() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (5, Token::Hexadecimal("0xffffffff".to_string())),
+            (2, Token::Operator(Symbol::POW)),
+        ];
+        let result: Vec<String> = vec!["1 ** 4294967295".to_string(), "1".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_pow_negative_exponent() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("2".to_string())),
+            (6, Token::Decimal("1".to_string())),
+            (5, Token::Operator(Symbol::NEG)),
+            (2, Token::Operator(Symbol::POW)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::NegativeExponent(Span::new(2, 4)))
+        );
+
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("2".to_string())),
+            (6, Token::Decimal("0".to_string())),
+            (5, Token::Operator(Symbol::NOT)),
+            (2, Token::Operator(Symbol::POW)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::NegativeExponent(Span::new(2, 4)))
+        );
+    }
+
+    #[test]
+    fn expression_neg_pow() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("2".to_string())),
+            (6, Token::Decimal("2".to_string())),
+            (3, Token::Operator(Symbol::POW)),
+            (0, Token::Operator(Symbol::NEG)),
+        ];
+        let result: Vec<String> = vec![
+            "2 ** 2".to_string(),
+            "4".to_string(),
+            "-4".to_string(),
+            "-4".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_eq() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Hexadecimal("0xff".to_string())),
+            (8, Token::Decimal("255".to_string())),
+            (5, Token::Operator(Symbol::EQ)),
+        ];
+        let result: Vec<String> = vec!["255 == 255".to_string(), "1".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_lt_signed() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("1".to_string())),
+            (0, Token::Operator(Symbol::NEG)),
+            (5, Token::Decimal("0".to_string())),
+            (3, Token::Operator(Symbol::LT)),
+        ];
+        let result: Vec<String> = vec![
+            "-1".to_string(),
+            "-1".to_string(),
+            "-1 < 0".to_string(),
+            "1".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_lt_unsigned() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Hexadecimal("0xffffffff".to_string())),
+            (13, Token::Decimal("0".to_string())),
+            (11, Token::Operator(Symbol::LT)),
+        ];
+        let result: Vec<String> = vec!["4294967295 < 0".to_string(), "0".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_logical() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("4".to_string())),
+            (6, Token::Decimal("0".to_string())),
+            (5, Token::Operator(Symbol::LNOT)),
+            (2, Token::Operator(Symbol::LAND)),
+        ];
+        let result: Vec<String> = vec![
+            "!0".to_string(),
+            "1".to_string(),
+            "4 && 1".to_string(),
+            "1".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_logical_short_circuit() {
+        let mut evaluator = Evaluator::default();
+        // 0 && 1 / 0
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("0".to_string())),
+            (5, Token::Decimal("1".to_string())),
+            (9, Token::Decimal("0".to_string())),
+            (7, Token::Operator(Symbol::DIV)),
+            (2, Token::Operator(Symbol::LAND)),
+        ];
+        let result: Vec<String> = vec!["0 && ...".to_string(), "0".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        // 2 || 1 / 0
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("2".to_string())),
+            (5, Token::Decimal("1".to_string())),
+            (9, Token::Decimal("0".to_string())),
+            (7, Token::Operator(Symbol::DIV)),
+            (2, Token::Operator(Symbol::LOR)),
+        ];
+        let result: Vec<String> = vec!["2 || ...".to_string(), "1".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        // 1 && 1 / 0
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (5, Token::Decimal("1".to_string())),
+            (9, Token::Decimal("0".to_string())),
+            (7, Token::Operator(Symbol::DIV)),
+            (2, Token::Operator(Symbol::LAND)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::DivisionByZero(Span::new(7, 8)))
+        );
+    }
+
+    #[test]
+    fn expression_rotl() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (0, Token::Hexadecimal("0x81".to_string())),
+            (9, Token::Decimal("1".to_string())),
+            (5, Token::Operator(Symbol::ROTL)),
+        ];
+        let result: Vec<String> = vec![
+            "129 <<< 1: 0b10000001 -> 0b00000011".to_string(),
+            "3".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_rotr_wraps_amount() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (6, Token::Decimal("9".to_string())),
+            (2, Token::Operator(Symbol::ROTR)),
+        ];
+        let result: Vec<String> = vec![
+            "1 >>> 9: 0b00000001 -> 0b10000000".to_string(),
+            "128".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn expression_rotate_unbounded() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::Unbounded);
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (6, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::ROTL)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::FixedWidthRequired(Span::new(2, 5)))
+        );
+    }
+
+    #[test]
+    fn call_popcount() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (9, Token::Hexadecimal("0xf0f".to_string())),
+            (0, Token::Call("popcount".to_string(), 1)),
+        ];
+        let result: Vec<String> = vec!["popcount(3855)".to_string(), "8".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn call_clz_ctz() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W16);
+        let tokens: Tokens = vec![
+            (4, Token::Hexadecimal("0x0f0".to_string())),
+            (0, Token::Call("clz".to_string(), 1)),
+            (17, Token::Hexadecimal("0x0f0".to_string())),
+            (13, Token::Call("ctz".to_string(), 1)),
+            (11, Token::Operator(Symbol::ADD)),
+        ];
+        let result: Vec<String> = vec![
+            "clz(240)".to_string(),
+            "8".to_string(),
+            "ctz(240)".to_string(),
+            "4".to_string(),
+            "8 + 4".to_string(),
+            "12".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn call_bswap_bitrev() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (6, Token::Hexadecimal("0x12345678".to_string())),
+            (0, Token::Call("bswap".to_string(), 1)),
+        ];
+        let result: Vec<String> = vec!["bswap(305419896)".to_string(), "2018915346".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (7, Token::Decimal("1".to_string())),
+            (0, Token::Call("bitrev".to_string(), 1)),
+        ];
+        let result: Vec<String> = vec!["bitrev(1)".to_string(), "128".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn call_pow2() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (5, Token::Decimal("1000".to_string())),
+            (0, Token::Call("log2".to_string(), 1)),
+            (22, Token::Decimal("1000".to_string())),
+            (13, Token::Call("nextpow2".to_string(), 1)),
+            (30, Token::Operator(Symbol::ADD)),
+        ];
+        let result: Vec<String> = vec![
+            "log2(1000)".to_string(),
+            "9".to_string(),
+            "nextpow2(1000)".to_string(),
+            "1024".to_string(),
+            "9 + 1024".to_string(),
+            "1033".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn call_log2_zero() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (5, Token::Decimal("0".to_string())),
+            (0, Token::Call("log2".to_string(), 1)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::Domain(Span::new(0, 4)))
+        );
+    }
+
+    #[test]
+    fn call_unknown() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (4, Token::Decimal("1".to_string())),
+            (0, Token::Call("rust".to_string(), 1)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::UnknownFunction(Span::new(0, 4)))
+        );
+    }
+
+    #[test]
+    fn call_arity() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![(0, Token::Call("popcount".to_string(), 0))];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::MissingArgument(Span::new(0, 8)))
+        );
+
+        let tokens: Tokens = vec![
+            (9, Token::Decimal("1".to_string())),
+            (12, Token::Decimal("2".to_string())),
+            (0, Token::Call("popcount".to_string(), 2)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::TooManyArguments(Span::new(12, 13)))
+        );
+    }
+
+    #[test]
+    fn call_masks() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (8, Token::Decimal("15".to_string())),
+            (12, Token::Decimal("8".to_string())),
+            (0, Token::Call("GENMASK".to_string(), 2)),
+            (20, Token::Decimal("4".to_string())),
+            (16, Token::Call("BIT".to_string(), 1)),
+            (14, Token::Operator(Symbol::OR)),
+        ];
+        let result: Vec<String> = vec![
+            "GENMASK(15, 8)".to_string(),
+            "65280".to_string(),
+            "BIT(4)".to_string(),
+            "16".to_string(),
+            "65280 | 16".to_string(),
+            "65296".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn call_fields() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (10, Token::Hexadecimal("0xff00".to_string())),
+            (18, Token::Hexadecimal("0xabcd".to_string())),
+            (0, Token::Call("FIELD_GET".to_string(), 2)),
+        ];
+        let result: Vec<String> = vec!["FIELD_GET(65280, 43981)".to_string(), "171".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        let tokens: Tokens = vec![
+            (11, Token::Hexadecimal("0xf0".to_string())),
+            (17, Token::Hexadecimal("0x1a".to_string())),
+            (0, Token::Call("FIELD_PREP".to_string(), 2)),
+        ];
+        let result: Vec<String> = vec!["FIELD_PREP(240, 26)".to_string(), "160".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        let tokens: Tokens = vec![
+            (10, Token::Decimal("0".to_string())),
+            (13, Token::Decimal("1".to_string())),
+            (0, Token::Call("FIELD_GET".to_string(), 2)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::Domain(Span::new(0, 9)))
+        );
+    }
+
+    #[test]
+    fn call_single_bits() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (7, Token::Decimal("0".to_string())),
+            (10, Token::Decimal("3".to_string())),
+            (0, Token::Call("setbit".to_string(), 2)),
+            (23, Token::Decimal("0".to_string())),
+            (14, Token::Call("togglebit".to_string(), 2)),
+            (35, Token::Decimal("3".to_string())),
+            (26, Token::Call("clearbit".to_string(), 2)),
+            (46, Token::Decimal("0".to_string())),
+            (38, Token::Call("testbit".to_string(), 2)),
+        ];
+        let result: Vec<String> = vec![
+            "setbit(0, 3)".to_string(),
+            "8".to_string(),
+            "togglebit(8, 0)".to_string(),
+            "9".to_string(),
+            "clearbit(9, 3)".to_string(),
+            "1".to_string(),
+            "testbit(1, 0)".to_string(),
+            "1".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn call_extract_insert() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (8, Token::Hexadecimal("0xdeadbeef".to_string())),
+            (20, Token::Decimal("15".to_string())),
+            (24, Token::Decimal("8".to_string())),
+            (0, Token::Call("extract".to_string(), 3)),
+        ];
+        let result: Vec<String> = vec!["extract(3735928559, 15, 8)".to_string(), "190".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        let tokens: Tokens = vec![
+            (7, Token::Hexadecimal("0xffff".to_string())),
+            (15, Token::Decimal("0".to_string())),
+            (18, Token::Decimal("11".to_string())),
+            (22, Token::Decimal("4".to_string())),
+            (0, Token::Call("insert".to_string(), 4)),
+        ];
+        let result: Vec<String> = vec!["insert(65535, 0, 11, 4)".to_string(), "61455".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn call_mask_bounds() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (4, Token::Decimal("32".to_string())),
+            (0, Token::Call("BIT".to_string(), 1)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::BitOutOfRange(Span::new(0, 3)))
+        );
+
+        let tokens: Tokens = vec![
+            (8, Token::Decimal("4".to_string())),
+            (11, Token::Decimal("8".to_string())),
+            (0, Token::Call("GENMASK".to_string(), 2)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::ReversedBounds(Span::new(0, 7)))
+        );
+    }
+
+    #[test]
+    fn slice() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Hexadecimal("0xdeadbeef".to_string())),
+            (11, Token::Decimal("15".to_string())),
+            (14, Token::Decimal("8".to_string())),
+            (10, Token::Operator(Symbol::SLICE)),
+            (17, Token::Decimal("3".to_string())),
+            (16, Token::Operator(Symbol::INDEX)),
+        ];
+        let result: Vec<String> = vec![
+            "3735928559[15:8]".to_string(),
+            "190".to_string(),
+            "190[3]".to_string(),
+            "1".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn slice_bounds() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![
+            (0, Token::Hexadecimal("0xff".to_string())),
+            (5, Token::Decimal("8".to_string())),
+            (7, Token::Decimal("0".to_string())),
+            (4, Token::Operator(Symbol::SLICE)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::BitOutOfRange(Span::new(4, 5)))
+        );
+
+        let tokens: Tokens = vec![
+            (0, Token::Hexadecimal("0xff".to_string())),
+            (5, Token::Decimal("0".to_string())),
+            (7, Token::Decimal("7".to_string())),
+            (4, Token::Operator(Symbol::SLICE)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::ReversedBounds(Span::new(4, 5)))
+        );
+    }
+
+    #[test]
+    fn assignment() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (7, Token::Hexadecimal("0xff".to_string())),
+            (15, Token::Decimal("8".to_string())),
+            (12, Token::Operator(Symbol::LSHIFT)),
+            (0, Token::Assignment("mask".to_string())),
+        ];
+        let result: Vec<String> = vec![
+            "255 << 8".to_string(),
+            "65280".to_string(),
+            "mask = 65280".to_string(),
+            "65280".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("mask".to_string())),
+            (7, Token::Hexadecimal("0xf0f0".to_string())),
+            (5, Token::Operator(Symbol::AND)),
+        ];
+        let result: Vec<String> = vec!["65280 & 61680".to_string(), "61440".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn assignment_width_change() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_signed(true);
+        let tokens: Tokens = vec![
+            (4, Token::Decimal("2064".to_string())),
+            (0, Token::Assignment("x".to_string())),
+        ];
+        evaluator.evaluate(statement(tokens)).unwrap();
+        let tokens: Tokens = vec![
+            (5, Token::Decimal("1".to_string())),
+            (4, Token::Operator(Symbol::NEG)),
+            (0, Token::Assignment("y".to_string())),
+        ];
+        evaluator.evaluate(statement(tokens)).unwrap();
+
+        // Still -1 in a narrower width
+        evaluator.set_width(Width::W8);
+        let tokens: Tokens = vec![(0, Token::Identifier("y".to_string()))];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), vec!["-1".to_string()]);
+
+        let tokens: Tokens = vec![(0, Token::Identifier("x".to_string()))];
+        let result: Vec<String> = vec![
+            "x is 2064, truncated to signed 8-bit".to_string(),
+            "16".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        evaluator.set_signed(false);
+        let tokens: Tokens = vec![(0, Token::Identifier("y".to_string()))];
+        let result: Vec<String> = vec![
+            "y is -1, truncated to unsigned 8-bit".to_string(),
+            "255".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn assignment_failed() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (4, Token::Decimal("1".to_string())),
+            (6, Token::Decimal("2".to_string())),
+            (0, Token::Assignment("a".to_string())),
+        ];
+        assert_eq!(ast::statement(&tokens), Err(ParserError::TooManyArguments(Span::new(6, 7))));
+
+        let tokens: Tokens = vec![(0, Token::Identifier("a".to_string()))];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::UnknownVariable(Span::new(0, 1)))
+        );
+    }
+
+    #[test]
+    fn slots() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![(0, Token::Decimal("6".to_string()))];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), vec!["6".to_string()]);
+        let tokens: Tokens = vec![
+            (0, Token::LastResult),
+            (4, Token::Decimal("7".to_string())),
+            (2, Token::Operator(Symbol::MUL)),
+        ];
+        evaluator.evaluate(statement(tokens)).unwrap();
+        assert_eq!(evaluator.slots(), 2);
+
+        let tokens: Tokens = vec![
+            (0, Token::Slot(1)),
+            (5, Token::LastResult),
+            (3, Token::Operator(Symbol::ADD)),
+        ];
+        let result: Vec<String> = vec!["6 + 42".to_string(), "48".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn slot_mode_change() {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(Width::Unbounded);
+        let tokens: Tokens = vec![(0, Token::Decimal("300".to_string()))];
+        evaluator.evaluate(statement(tokens)).unwrap();
+
+        evaluator.set_width(Width::W8);
+        evaluator.set_signed(true);
+        let tokens: Tokens = vec![
+            (1, Token::Decimal("1".to_string())),
+            (0, Token::Operator(Symbol::NEG)),
+        ];
+        evaluator.evaluate(statement(tokens)).unwrap();
+        let tokens: Tokens = vec![(0, Token::Slot(1))];
+        let result: Vec<String> = vec![
+            "$1 is 300, truncated to signed 8-bit".to_string(),
+            "44".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        evaluator.set_signed(false);
+        let tokens: Tokens = vec![(0, Token::Slot(2))];
+        let result: Vec<String> = vec![
+            "$2 is -1, truncated to unsigned 8-bit".to_string(),
+            "255".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        // The last result fits, so it is used as it is
+        let tokens: Tokens = vec![(0, Token::LastResult)];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), vec!["255".to_string()]);
+    }
+
+    #[test]
+    fn slot_unknown() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![(0, Token::LastResult)];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::UnknownSlot(Span::new(0, 1)))
+        );
+
+        let tokens: Tokens = vec![(0, Token::Decimal("1".to_string()))];
+        evaluator.evaluate(statement(tokens)).unwrap();
+        let tokens: Tokens = vec![(0, Token::Slot(0))];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::UnknownSlot(Span::new(0, 2)))
+        );
+        let tokens: Tokens = vec![(0, Token::Slot(2))];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::UnknownSlot(Span::new(0, 2)))
+        );
+    }
+
+    fn align_up() -> Statement {
+        // def align_up(x, a) = (x + a - 1) & ~(a - 1)
+        let definition = Definition {
+            params: vec!["x".to_string(), "a".to_string()],
+            body: expression(vec![
+                (22, Token::Identifier("x".to_string())),
+                (26, Token::Identifier("a".to_string())),
+                (24, Token::Operator(Symbol::ADD)),
+                (30, Token::Decimal("1".to_string())),
+                (28, Token::Operator(Symbol::SUB)),
+                (37, Token::Identifier("a".to_string())),
+                (41, Token::Decimal("1".to_string())),
+                (39, Token::Operator(Symbol::SUB)),
+                (35, Token::Operator(Symbol::NOT)),
+                (33, Token::Operator(Symbol::AND)),
+            ]),
+            text: "(x + a - 1) & ~(a - 1)".to_string(),
+        };
+        define(4, "align_up", definition)
+    }
+
+    #[test]
+    fn definition_call() {
+        let mut evaluator = Evaluator::default();
+        let result: Vec<String> = vec!["Defined align_up(x, a)".to_string()];
+        assert_eq!(evaluator.evaluate(align_up()).unwrap(), result);
+
+        let tokens: Tokens = vec![
+            (9, Token::Decimal("10".to_string())),
+            (13, Token::Decimal("8".to_string())),
+            (0, Token::Call("align_up".to_string(), 2)),
+        ];
+        let result: Vec<String> = vec![
+            "10 + 8".to_string(),
+            "18".to_string(),
+            "18 - 1".to_string(),
+            "17".to_string(),
+            "8 - 1".to_string(),
+            "7".to_string(),
+            "~7".to_string(),
+            "4294967288".to_string(),
+            "17 & 4294967288".to_string(),
+            "16".to_string(),
+            "align_up(10, 8)".to_string(),
+            "16".to_string(),
+        ];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+
+        let tokens: Tokens = vec![(0, Token::Keyword("defs".to_string()))];
+        let result: Vec<String> = vec!["align_up(x, a) = (x + a - 1) & ~(a - 1)".to_string()];
+        assert_eq!(evaluator.evaluate(statement(tokens)).unwrap(), result);
+    }
+
+    #[test]
+    fn definition_arity() {
+        let mut evaluator = Evaluator::default();
+        evaluator.evaluate(align_up()).unwrap();
+        let tokens: Tokens = vec![
+            (9, Token::Decimal("10".to_string())),
+            (0, Token::Call("align_up".to_string(), 1)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::MissingArgument(Span::new(0, 8)))
+        );
+
+        let tokens: Tokens = vec![
+            (9, Token::Decimal("1".to_string())),
+            (12, Token::Decimal("2".to_string())),
+            (15, Token::Decimal("3".to_string())),
+            (0, Token::Call("align_up".to_string(), 3)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::TooManyArguments(Span::new(15, 16)))
+        );
+    }
+
+    #[test]
+    fn definition_error_position() {
+        let mut evaluator = Evaluator::default();
+        let definition = Definition {
+            params: vec!["x".to_string()],
+            body: expression(vec![
+                (12, Token::Identifier("x".to_string())),
+                (16, Token::Decimal("0".to_string())),
+                (14, Token::Operator(Symbol::DIV)),
+            ]),
+            text: "x / 0".to_string(),
+        };
+        let tokens = define(4, "f", definition);
+        evaluator.evaluate(tokens).unwrap();
+
+        let tokens: Tokens = vec![
+            (6, Token::Decimal("1".to_string())),
+            (4, Token::Call("f".to_string(), 1)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::DivisionByZero(Span::new(4, 5)))
+        );
+    }
+
+    #[test]
+    fn definition_narrowed_width() {
+        let mut evaluator = Evaluator::default();
+        let definition = Definition {
+            params: vec![],
+            body: expression(vec![(10, Token::Hexadecimal("0x1ff".to_string()))]),
+            text: "0x1ff".to_string(),
+        };
+        evaluator.evaluate(define(4, "f", definition)).unwrap();
+        let tokens: Tokens = vec![
+            (0, Token::Keyword("width".to_string())),
+            (6, Token::Decimal("8".to_string())),
+        ];
+        evaluator.evaluate(statement(tokens)).unwrap();
+
+        let tokens: Tokens = vec![
+            (4, Token::Call("f".to_string(), 0)),
+            (0, Token::Call("clz".to_string(), 1)),
+        ];
+        let result = evaluator.evaluate(statement(tokens)).unwrap();
+        assert_eq!(result.last().unwrap(), "0");
+
+        let tokens: Tokens = vec![
+            (0, Token::Call("f".to_string(), 0)),
+            (6, Token::Decimal("0".to_string())),
+            (4, Token::Operator(Symbol::ADD)),
+        ];
+        let result = evaluator.evaluate(statement(tokens)).unwrap();
+        assert_eq!(result.last().unwrap(), "255");
+    }
+
+    #[test]
+    fn definition_recursion() {
+        let mut evaluator = Evaluator::default();
+        let call = |callee: &str| Definition {
+            params: vec!["x".to_string()],
+            body: expression(vec![
+                (12, Token::Identifier("x".to_string())),
+                (10, Token::Call(callee.to_string(), 1)),
+            ]),
+            text: format!("{}(x)", callee),
+        };
+
+        let tokens = define(4, "f", call("f"));
+        assert_eq!(evaluator.evaluate(tokens), Err(EvaluatorError::Recursion(Span::new(4, 5))));
+
+        let tokens = define(4, "f", call("g"));
+        evaluator.evaluate(tokens).unwrap();
+        let tokens = define(4, "g", call("f"));
+        assert_eq!(evaluator.evaluate(tokens), Err(EvaluatorError::Recursion(Span::new(4, 5))));
+
+        let tokens = define(4, "popcount", call("f"));
+        assert_eq!(evaluator.evaluate(tokens), Err(EvaluatorError::ReservedName(Span::new(4, 12))));
+    }
+
+    #[test]
+    fn definition_depth() {
+        let mut evaluator = Evaluator::default();
+        // def f(x) = ~~...~x, as deep as a definition may be
+        let mut tokens: Tokens = vec![(MAX_DEPTH + 9, Token::Identifier("x".to_string()))];
+        tokens.extend((0..MAX_DEPTH - 2).rev().map(|i| (i + 11, Token::Operator(Symbol::NOT))));
+        let definition = Definition {
+            params: vec!["x".to_string()],
+            body: expression(tokens),
+            text: format!("{}x", "~".repeat(MAX_DEPTH - 2)),
+        };
+        evaluator.evaluate(define(4, "f", definition)).unwrap();
+
+        let tokens: Tokens = vec![
+            (2, Token::Decimal("1".to_string())),
+            (0, Token::Call("f".to_string(), 1)),
+        ];
+        let result = evaluator.evaluate(statement(tokens)).unwrap();
+        assert_eq!(result.last().unwrap(), "1");
+
+        // Each call stays within the limit, but calling f from g nests past it
+        let definition = Definition {
+            params: vec!["x".to_string()],
+            body: expression(vec![
+                (14, Token::Identifier("x".to_string())),
+                (12, Token::Call("f".to_string(), 1)),
+                (11, Token::Operator(Symbol::NOT)),
+            ]),
+            text: "~f(x)".to_string(),
+        };
+        assert_eq!(
+            evaluator.evaluate(define(4, "g", definition)),
+            Err(EvaluatorError::TooDeep(Span::new(12, 13)))
+        );
+
+        let tokens: Tokens = vec![
+            (4, Token::Decimal("1".to_string())),
+            (2, Token::Call("f".to_string(), 1)),
+            (0, Token::Operator(Symbol::NOT)),
+        ];
+        assert_eq!(
+            evaluator.evaluate(statement(tokens)),
+            Err(EvaluatorError::TooDeep(Span::new(2, 3)))
+        );
+    }
+
+    #[test]
+    fn preview() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (4, Token::Decimal("3".to_string())),
+            (0, Token::Assignment("a".to_string())),
+        ];
+        assert_eq!(evaluator.preview(&statement(tokens)), Some(BigInt::from(3)));
+        assert_eq!(evaluator.slots(), 0);
+        let tokens: Tokens = vec![(0, Token::Identifier("a".to_string()))];
+        assert_eq!(evaluator.preview(&statement(tokens)), None);
+
+        let tokens: Tokens = vec![(0, Token::Keyword("exit".to_string()))];
+        assert_eq!(evaluator.preview(&statement(tokens)), None);
+
+        let tokens: Tokens = vec![
+            (4, Token::Decimal("3".to_string())),
+            (0, Token::Assignment("a".to_string())),
+        ];
+        evaluator.evaluate(statement(tokens)).unwrap();
+        let snapshot = evaluator.snapshot();
+        let tokens: Tokens = vec![(0, Token::Identifier("a".to_string()))];
+        assert_eq!(snapshot.preview(&statement(tokens)), Some(BigInt::from(3)));
+        assert_eq!(snapshot.slots(), 1);
+    }
+
+    #[test]
+    fn apply() {
+        let mut evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (1, Token::Identifier("x".to_string())),
+            (6, Token::Decimal("4".to_string())),
+            (3, Token::Operator(Symbol::RSHIFT)),
+        ];
+        let locals = vec![("x".to_string(), BigInt::from(0x1234))].into_iter().collect();
+        assert_eq!(evaluator.apply(&expression(tokens.clone()), &locals), Ok(BigInt::from(0x123)));
+        assert_eq!(evaluator.slots(), 0);
+
+        let tokens: Tokens = vec![
+            (4, Token::Decimal("7".to_string())),
+            (0, Token::Assignment("x".to_string())),
+        ];
+        evaluator.evaluate(statement(tokens)).unwrap();
+        let tokens: Tokens = vec![(0, Token::Identifier("x".to_string()))];
+        assert_eq!(evaluator.apply(&expression(tokens.clone()), &locals), Ok(BigInt::from(0x1234)));
+    }
+
+    #[test]
+    fn check() {
+        let evaluator = Evaluator::default();
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("x".to_string())),
+            (4, Token::Decimal("0".to_string())),
+            (2, Token::Operator(Symbol::DIV)),
+        ];
+        assert_eq!(evaluator.check(&expression(tokens), &["x"]), Ok(()));
+
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("x".to_string())),
+            (4, Token::Identifier("y".to_string())),
+            (2, Token::Operator(Symbol::ADD)),
+        ];
+        assert_eq!(
+            evaluator.check(&expression(tokens), &["x"]),
+            Err(EvaluatorError::UnknownVariable(Span::new(4, 5)))
+        );
+
+        let tokens: Tokens = vec![
+            (9, Token::Identifier("x".to_string())),
+            (0, Token::Call("popcnt".to_string(), 1)),
+        ];
+        assert_eq!(
+            evaluator.check(&expression(tokens), &["x"]),
+            Err(EvaluatorError::UnknownFunction(Span::new(0, 6)))
+        );
+
+        let tokens: Tokens = vec![
+            (9, Token::Identifier("x".to_string())),
+            (12, Token::Decimal("1".to_string())),
+            (0, Token::Call("popcount".to_string(), 2)),
+        ];
+        assert_eq!(
+            evaluator.check(&expression(tokens), &["x"]),
+            Err(EvaluatorError::TooManyArguments(Span::new(12, 13)))
+        );
+
+        let tokens: Tokens = vec![(0, Token::LastResult)];
+        assert_eq!(
+            evaluator.check(&expression(tokens), &["x"]),
+            Err(EvaluatorError::UnknownSlot(Span::new(0, 1)))
+        );
+    }
 }
@@ -1,86 +1,552 @@
 use std::collections::HashMap;
+use num_bigint::BigInt;
 use error::EvaluatorError;
+use ast::{Expr, Span};
 use lexer::Symbol;
+use mode::Mode;
 
-type Operation = (u32, String);
+type Operation = (BigInt, String);
 pub type Functions = HashMap<Symbol, Function>;
-pub type FunctionHandle = Box<Fn(Vec<u32>, usize) -> Result<Operation, EvaluatorError>>;
+pub type FunctionHandle = Box<dyn Fn(Vec<BigInt>, Span, Mode) -> Result<Operation, EvaluatorError>>;
+
+// A function written in the REPL with `def name(params) = body`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Definition {
+    pub params: Vec<String>,
+    pub body: Expr,
+    pub text: String, // Body as it was typed, for listing
+}
+
+pub enum Body {
+    Builtin(FunctionHandle),
+    Defined(Definition),
+}
 
 pub struct Function {
     pub arity: usize,
-    pub handle: FunctionHandle,
+    pub body: Body,
+    pub depth: usize, // How deeply computing the body nests, none for builtins
 }
 
 impl Function {
     pub fn new(a: usize, h: FunctionHandle) -> Function {
         Function {
             arity: a,
-            handle: h,
+            body: Body::Builtin(h),
+            depth: 0,
+        }
+    }
+
+    pub fn defined(definition: Definition, depth: usize) -> Function {
+        Function {
+            arity: definition.params.len(),
+            body: Body::Defined(definition),
+            depth,
         }
     }
 }
 
 pub mod functions {
+    use num_bigint::{BigInt, Sign};
+    use num_integer::Integer;
+    use num_traits::{One, Signed, ToPrimitive, Zero};
+    use ast::Span;
     use error::EvaluatorError;
-    type Operation = (u32, String);
+    use mode::{Mode, MAX_UNBOUNDED_BITS};
+    type Operation = (BigInt, String);
+
+    fn shift_amount(amount: &BigInt, span: Span, mode: Mode) -> Result<u64, EvaluatorError> {
+        if amount.sign() == Sign::Minus {
+            return Err(EvaluatorError::NegativeShift(span));
+        }
+        match mode.width.bits() {
+            Some(bits) => match amount.to_u64() {
+                Some(amount) if amount < u64::from(bits) => Ok(amount),
+                _ => Err(EvaluatorError::OverflowShift(span)),
+            },
+            // Any shift past the size limit gives the same result as one at it
+            None => Ok(amount.to_u64().map_or(MAX_UNBOUNDED_BITS, |amount| amount.min(MAX_UNBOUNDED_BITS))),
+        }
+    }
+
+    // Results without a fixed width may grow up to the size limit
+    fn bounded(bits: u64, span: Span, mode: Mode) -> Result<(), EvaluatorError> {
+        if mode.width.bits().is_none() && bits > MAX_UNBOUNDED_BITS {
+            return Err(EvaluatorError::TooLarge(span));
+        }
+        Ok(())
+    }
+
+    // Wrap up the exact result of an arithmetic operation, which must fit the mode
+    fn arithmetic(value: BigInt, span: Span, mode: Mode) -> Result<BigInt, EvaluatorError> {
+        bounded(value.bits(), span, mode)?;
+        if !mode.contains(&value) {
+            return Err(EvaluatorError::Overflow(span));
+        }
+        Ok(mode.width.mask(value))
+    }
+
+    pub fn not(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let a = args.pop().unwrap();
+        Ok((mode.width.mask(!&a), format!("~{}", mode.decimal(&a))))
+    }
+
+    pub fn neg(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let a = args.pop().unwrap();
+        let operand = mode.decimal(&a);
+        let trace = if operand.starts_with('-') {
+            format!("-({})", operand)
+        } else {
+            format!("-{}", operand)
+        };
+        Ok((mode.width.mask(-a), trace))
+    }
+
+    pub fn and(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let trace = format!("{} & {}", mode.decimal(&a), mode.decimal(&b));
+        Ok((a & b, trace))
+    }
+
+    pub fn or(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let trace = format!("{} | {}", mode.decimal(&a), mode.decimal(&b));
+        Ok((a | b, trace))
+    }
 
-    pub fn not(mut args: Vec<u32>, _position: usize) -> Result<Operation, EvaluatorError> {
-        let a: u32 = args.pop().unwrap();
-        Ok((!a, format!("{}{}", "~".to_string(), a.to_string())))
+    pub fn xor(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let trace = format!("{} ^ {}", mode.decimal(&a), mode.decimal(&b));
+        Ok((a ^ b, trace))
     }
 
-    pub fn and(mut args: Vec<u32>, _position: usize) -> Result<Operation, EvaluatorError> {
+    pub fn rshift(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
         let b = args.pop().unwrap();
         let a = args.pop().unwrap();
-        Ok((
-            a & b,
-            format!("{} {} {}", a.to_string(), "&".to_string(), b.to_string()),
-        ))
+        let amount = shift_amount(&mode.value(&b), span, mode)?;
+
+        // Shifting the signed value rounds towards negative infinity,
+        // which makes the shift arithmetic in signed and unbounded modes
+        let c = mode.width.mask(mode.value(&a) >> amount);
+        Ok((c, format!("{} >> {}", mode.decimal(&a), mode.decimal(&b))))
     }
 
-    pub fn or(mut args: Vec<u32>, _position: usize) -> Result<Operation, EvaluatorError> {
+    pub fn lshift(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
         let b = args.pop().unwrap();
         let a = args.pop().unwrap();
-        Ok((
-            a | b,
-            format!("{} {} {}", a.to_string(), "|".to_string(), b.to_string()),
-        ))
+        let amount = shift_amount(&mode.value(&b), span, mode)?;
+        if !a.is_zero() {
+            bounded(a.bits() + amount, span, mode)?;
+        }
+
+        let c = mode.width.mask(&a << amount);
+        Ok((c, format!("{} << {}", mode.decimal(&a), mode.decimal(&b))))
     }
 
-    pub fn xor(mut args: Vec<u32>, _position: usize) -> Result<Operation, EvaluatorError> {
+    // Rotations wrap the amount modulo the width, so they need a fixed one
+    fn rotation(amount: &BigInt, span: Span, mode: Mode) -> Result<(usize, usize), EvaluatorError> {
+        match mode.width.bits() {
+            Some(bits) => {
+                let amount = amount.mod_floor(&BigInt::from(bits)).to_usize().unwrap();
+                Ok((bits as usize, amount))
+            }
+            None => Err(EvaluatorError::FixedWidthRequired(span)),
+        }
+    }
+
+    // Show the bit pattern before and after, so the wrapped bits are easy to follow
+    fn rotation_trace(a: &BigInt, b: &BigInt, c: &BigInt, symbol: &str, bits: usize, mode: Mode) -> String {
+        format!(
+            "{} {} {}: {:#0width$b} -> {:#0width$b}",
+            mode.decimal(a),
+            symbol,
+            mode.decimal(b),
+            a,
+            c,
+            width = bits + 2
+        )
+    }
+
+    pub fn rotl(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
         let b = args.pop().unwrap();
         let a = args.pop().unwrap();
-        Ok((
-            a ^ b,
-            format!("{} {} {}", a.to_string(), "^".to_string(), b.to_string()),
-        ))
+        let (bits, amount) = rotation(&mode.value(&b), span, mode)?;
+
+        let c = mode.width.mask((&a << amount) | (&a >> (bits - amount)));
+        let trace = rotation_trace(&a, &b, &c, "<<<", bits, mode);
+        Ok((c, trace))
     }
 
-    pub fn rshift(mut args: Vec<u32>, _position: usize) -> Result<Operation, EvaluatorError> {
+    pub fn rotr(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
         let b = args.pop().unwrap();
         let a = args.pop().unwrap();
+        let (bits, amount) = rotation(&mode.value(&b), span, mode)?;
 
-        if let Some(c) = a.checked_shr(b as u32) {
-            return Ok((
-                c,
-                format!("{} {} {}", a.to_string(), ">>".to_string(), b.to_string()),
-            ));
+        let c = mode.width.mask((&a >> amount) | (&a << (bits - amount)));
+        let trace = rotation_trace(&a, &b, &c, ">>>", bits, mode);
+        Ok((c, trace))
+    }
+
+    pub fn add(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let c = arithmetic(mode.value(&a) + mode.value(&b), span, mode)?;
+        Ok((c, format!("{} + {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn sub(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let c = arithmetic(mode.value(&a) - mode.value(&b), span, mode)?;
+        Ok((c, format!("{} - {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn mul(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let c = arithmetic(mode.value(&a) * mode.value(&b), span, mode)?;
+        Ok((c, format!("{} * {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    // Fixed widths truncate towards zero like C, unbounded values round down like Python
+    pub fn div(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        if b.is_zero() {
+            return Err(EvaluatorError::DivisionByZero(span));
+        }
+
+        let (x, y) = (mode.value(&a), mode.value(&b));
+        let quotient = if mode.width.bits().is_some() {
+            x / y
         } else {
-            return Err(EvaluatorError::OverflowShift(_position));
+            x.div_floor(&y)
+        };
+        let c = arithmetic(quotient, span, mode)?;
+        Ok((c, format!("{} / {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn rem(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        if b.is_zero() {
+            return Err(EvaluatorError::DivisionByZero(span));
         }
+
+        let (x, y) = (mode.value(&a), mode.value(&b));
+        let remainder = if mode.width.bits().is_some() {
+            x % y
+        } else {
+            x.mod_floor(&y)
+        };
+        let c = arithmetic(remainder, span, mode)?;
+        Ok((c, format!("{} % {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn pow(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let (base, exponent) = (mode.value(&a), mode.value(&b));
+        if exponent.is_negative() {
+            return Err(EvaluatorError::NegativeExponent(span));
+        }
+
+        // Only 0, 1 and -1 can be raised to a huge power without the result
+        // outgrowing the width, or the size limit when the width is unbounded
+        let power = if base.abs() <= BigInt::from(1) {
+            if base.is_negative() && exponent.is_odd() {
+                base
+            } else if base.is_zero() && !exponent.is_zero() {
+                BigInt::zero()
+            } else {
+                BigInt::from(1)
+            }
+        } else {
+            // The result has at least this many bits beyond its lowest one
+            let bits = exponent.to_u64().map_or(u64::MAX, |exp| exp.saturating_mul(base.bits() - 1));
+            match mode.width.bits() {
+                Some(width) if bits >= u64::from(width) => return Err(EvaluatorError::Overflow(span)),
+                None => bounded(bits.saturating_add(1), span, mode)?,
+                _ => (),
+            }
+            base.pow(exponent.to_u32().unwrap())
+        };
+        let c = arithmetic(power, span, mode)?;
+        Ok((c, format!("{} ** {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    fn boolean(value: bool) -> BigInt {
+        if value {
+            BigInt::from(1)
+        } else {
+            BigInt::zero()
+        }
+    }
+
+    pub fn eq(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        Ok((boolean(a == b), format!("{} == {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn ne(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        Ok((boolean(a != b), format!("{} != {}", mode.decimal(&a), mode.decimal(&b))))
     }
 
-    pub fn lshift(mut args: Vec<u32>, _position: usize) -> Result<Operation, EvaluatorError> {
+    pub fn lt(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
         let b = args.pop().unwrap();
         let a = args.pop().unwrap();
+        let c = boolean(mode.value(&a) < mode.value(&b));
+        Ok((c, format!("{} < {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn le(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let c = boolean(mode.value(&a) <= mode.value(&b));
+        Ok((c, format!("{} <= {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn gt(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let c = boolean(mode.value(&a) > mode.value(&b));
+        Ok((c, format!("{} > {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn ge(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let c = boolean(mode.value(&a) >= mode.value(&b));
+        Ok((c, format!("{} >= {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    // Only called when the left operand does not decide the result on its own
+    pub fn land(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let c = boolean(!a.is_zero() && !b.is_zero());
+        Ok((c, format!("{} && {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn lor(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let c = boolean(!a.is_zero() || !b.is_zero());
+        Ok((c, format!("{} || {}", mode.decimal(&a), mode.decimal(&b))))
+    }
+
+    pub fn lnot(mut args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let a = args.pop().unwrap();
+        Ok((boolean(a.is_zero()), format!("!{}", mode.decimal(&a))))
+    }
+
+    fn fixed_width(span: Span, mode: Mode) -> Result<u64, EvaluatorError> {
+        match mode.width.bits() {
+            Some(bits) => Ok(u64::from(bits)),
+            None => Err(EvaluatorError::FixedWidthRequired(span)),
+        }
+    }
+
+    fn call_trace(name: &str, args: &[BigInt], mode: Mode) -> String {
+        let args: Vec<String> = args.iter().map(|arg| mode.decimal(arg)).collect();
+        format!("{}({})", name, args.join(", "))
+    }
+
+    pub fn popcount(args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let count = args[0].magnitude().count_ones();
+        Ok((BigInt::from(count), call_trace("popcount", &args, mode)))
+    }
+
+    pub fn clz(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let bits = fixed_width(span, mode)?;
+        let count = match bits.checked_sub(args[0].bits()) {
+            Some(count) => count,
+            None => return Err(EvaluatorError::Domain(span)),
+        };
+        Ok((BigInt::from(count), call_trace("clz", &args, mode)))
+    }
+
+    pub fn ctz(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let count = match args[0].trailing_zeros() {
+            Some(count) => count,
+            None if mode.width.bits().is_some() => fixed_width(span, mode)?,
+            None => return Err(EvaluatorError::Domain(span)),
+        };
+        Ok((BigInt::from(count), call_trace("ctz", &args, mode)))
+    }
+
+    pub fn parity(args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let count = args[0].magnitude().count_ones();
+        Ok((BigInt::from(count & 1), call_trace("parity", &args, mode)))
+    }
+
+    pub fn bswap(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let bytes = (fixed_width(span, mode)? / 8) as usize;
+        let mut le_bytes = args[0].magnitude().to_bytes_le();
+        if le_bytes.len() > bytes {
+            return Err(EvaluatorError::Domain(span));
+        }
+        le_bytes.resize(bytes, 0);
+
+        // Reading the little-endian bytes as big-endian swaps them
+        let c = BigInt::from_bytes_be(Sign::Plus, &le_bytes);
+        Ok((c, call_trace("bswap", &args, mode)))
+    }
+
+    pub fn bitrev(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let bits = fixed_width(span, mode)?;
+        let mut c = BigInt::zero();
+        for bit in 0..bits {
+            if args[0].bit(bit) {
+                c.set_bit(bits - 1 - bit, true);
+            }
+        }
+        Ok((c, call_trace("bitrev", &args, mode)))
+    }
 
-        if let Some(c) = a.checked_shl(b as u32) {
-            return Ok((
-                c,
-                format!("{} {} {}", a.to_string(), "<<".to_string(), b.to_string()),
-            ));
+    pub fn log2(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let a = mode.value(&args[0]);
+        if !a.is_positive() {
+            return Err(EvaluatorError::Domain(span));
+        }
+        Ok((BigInt::from(a.bits() - 1), call_trace("log2", &args, mode)))
+    }
+
+    pub fn ispow2(args: Vec<BigInt>, _span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let a = mode.value(&args[0]);
+        let c = boolean(a.is_positive() && (&a & (&a - 1u32)).is_zero());
+        Ok((c, call_trace("ispow2", &args, mode)))
+    }
+
+    pub fn nextpow2(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let a = mode.value(&args[0]);
+        if a.is_negative() {
+            return Err(EvaluatorError::Domain(span));
+        }
+        let power = if a <= BigInt::one() {
+            BigInt::one()
         } else {
-            return Err(EvaluatorError::OverflowShift(_position));
+            BigInt::one() << (&a - 1u32).bits()
+        };
+        let c = arithmetic(power, span, mode)?;
+        Ok((c, call_trace("nextpow2", &args, mode)))
+    }
+
+    // Bit positions must lie inside the width, or below the size limit when unbounded
+    fn bit_index(index: &BigInt, span: Span, mode: Mode) -> Result<u64, EvaluatorError> {
+        let limit = match mode.width.bits() {
+            Some(bits) => u64::from(bits),
+            None => MAX_UNBOUNDED_BITS,
+        };
+        match mode.value(index).to_u64() {
+            Some(index) if index < limit => Ok(index),
+            _ => Err(EvaluatorError::BitOutOfRange(span)),
+        }
+    }
+
+    fn bit_range(hi: &BigInt, lo: &BigInt, span: Span, mode: Mode) -> Result<(u64, u64), EvaluatorError> {
+        let hi = bit_index(hi, span, mode)?;
+        let lo = bit_index(lo, span, mode)?;
+        if hi < lo {
+            return Err(EvaluatorError::ReversedBounds(span));
         }
+        Ok((hi, lo))
+    }
+
+    // Mask with bits hi down to lo set, inclusive
+    fn field_mask(hi: u64, lo: u64) -> BigInt {
+        ((BigInt::one() << (hi - lo + 1)) - 1) << lo
+    }
+
+    // Shift of a mask's lowest set bit, an empty mask selects no field
+    fn mask_shift(mask: &BigInt, span: Span) -> Result<u64, EvaluatorError> {
+        match mask.trailing_zeros() {
+            Some(shift) => Ok(shift),
+            None => Err(EvaluatorError::Domain(span)),
+        }
+    }
+
+    pub fn bit(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let n = bit_index(&args[0], span, mode)?;
+        Ok((BigInt::one() << n, call_trace("BIT", &args, mode)))
+    }
+
+    pub fn genmask(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let (hi, lo) = bit_range(&args[0], &args[1], span, mode)?;
+        Ok((field_mask(hi, lo), call_trace("GENMASK", &args, mode)))
+    }
+
+    pub fn field_get(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let shift = mask_shift(&args[0], span)?;
+        let c = (&args[1] & &args[0]) >> shift;
+        Ok((c, call_trace("FIELD_GET", &args, mode)))
+    }
+
+    pub fn field_prep(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let shift = mask_shift(&args[0], span)?;
+        let c = (&args[1] << shift) & &args[0];
+        Ok((c, call_trace("FIELD_PREP", &args, mode)))
+    }
+
+    pub fn setbit(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let n = bit_index(&args[1], span, mode)?;
+        let mut c = args[0].clone();
+        c.set_bit(n, true);
+        Ok((c, call_trace("setbit", &args, mode)))
+    }
+
+    pub fn clearbit(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let n = bit_index(&args[1], span, mode)?;
+        let mut c = args[0].clone();
+        c.set_bit(n, false);
+        Ok((c, call_trace("clearbit", &args, mode)))
+    }
+
+    pub fn togglebit(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let n = bit_index(&args[1], span, mode)?;
+        let mut c = args[0].clone();
+        c.set_bit(n, !args[0].bit(n));
+        Ok((c, call_trace("togglebit", &args, mode)))
+    }
+
+    pub fn testbit(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let n = bit_index(&args[1], span, mode)?;
+        Ok((boolean(args[0].bit(n)), call_trace("testbit", &args, mode)))
+    }
+
+    pub fn extract(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let (hi, lo) = bit_range(&args[1], &args[2], span, mode)?;
+        let c = (&args[0] & field_mask(hi, lo)) >> lo;
+        Ok((c, call_trace("extract", &args, mode)))
+    }
+
+    // Bits of the field beyond the range are dropped, as with FIELD_PREP
+    pub fn insert(args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let (hi, lo) = bit_range(&args[2], &args[3], span, mode)?;
+        let mask = field_mask(hi, lo);
+        let c = (&args[0] & !&mask) | ((&args[1] << lo) & &mask);
+        Ok((c, call_trace("insert", &args, mode)))
+    }
+
+    pub fn index(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let b = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let n = bit_index(&b, span, mode)?;
+        Ok((boolean(a.bit(n)), format!("{}[{}]", mode.decimal(&a), n)))
+    }
+
+    pub fn slice(mut args: Vec<BigInt>, span: Span, mode: Mode) -> Result<Operation, EvaluatorError> {
+        let lo = args.pop().unwrap();
+        let hi = args.pop().unwrap();
+        let a = args.pop().unwrap();
+        let (hi, lo) = bit_range(&hi, &lo, span, mode)?;
+        let c = (&a & field_mask(hi, lo)) >> lo;
+        Ok((c, format!("{}[{}:{}]", mode.decimal(&a), hi, lo)))
     }
 }
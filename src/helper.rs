@@ -0,0 +1,411 @@
+use std::borrow::Cow::{self, Borrowed, Owned};
+use std::env;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper, Result};
+use ast::Span;
+use constants::{COMMANDS, KEYWORDS, SIGNATURES};
+use error::LexerError;
+use evaluator::Evaluator;
+use lexer::{lexer, scan, Token, Tokens};
+use mode::Width;
+use parser::Parser;
+
+// ANSI colours for each kind of token
+const DECIMAL: &str = "32";
+const HEXADECIMAL: &str = "33";
+const OCTAL: &str = "36";
+const BINARY: &str = "35";
+const OPERATOR: &str = "34";
+const KEYWORD: &str = "1;95";
+const BRACKET: &str = "90";
+const MATCHING_BRACKET: &str = "1;7";
+const ERROR: &str = "31";
+const PREVIEW: &str = "2";
+
+struct Candidate {
+    name: String,
+    replacement: String,
+    signature: String,
+    command: bool, // Only completed as the first word of a line
+}
+
+// Result of the line so far, shown after the cursor but never inserted
+pub struct Preview(String);
+
+impl Hint for Preview {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+pub struct ReplHelper {
+    candidates: Vec<Candidate>,
+    width: Width,
+    colour: bool,
+    parser: Parser,
+    // Copy of the session, so previews can never change the real one
+    evaluator: Evaluator,
+}
+
+fn signature(name: &str) -> String {
+    match SIGNATURES.iter().find(|&&(entry, _, _)| entry == name) {
+        Some(&(_, signature, summary)) => format!("{:<24} {}", signature, summary),
+        None => name.to_string(),
+    }
+}
+
+impl ReplHelper {
+    pub fn new(evaluator: &Evaluator) -> ReplHelper {
+        let mut helper = ReplHelper {
+            candidates: Vec::new(),
+            width: evaluator.mode().width,
+            // https://no-color.org
+            colour: env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+            parser: Parser::default(),
+            evaluator: evaluator.snapshot(),
+        };
+        helper.refresh(evaluator);
+        helper
+    }
+
+    // Pick up the functions and variables defined since the last line
+    pub fn refresh(&mut self, evaluator: &Evaluator) {
+        self.width = evaluator.mode().width;
+        self.evaluator = evaluator.snapshot();
+        self.candidates.clear();
+        for &name in KEYWORDS.iter().chain(COMMANDS) {
+            self.candidates.push(Candidate {
+                name: name.to_string(),
+                replacement: name.to_string(),
+                signature: signature(name),
+                command: true,
+            });
+        }
+        for (name, definition) in evaluator.functions() {
+            self.candidates.push(Candidate {
+                replacement: format!("{}(", name),
+                signature: definition.unwrap_or_else(|| signature(&name)),
+                name,
+                command: false,
+            });
+        }
+        for (name, value) in evaluator.variables() {
+            self.candidates.push(Candidate {
+                replacement: name.clone(),
+                signature: format!("{} = {}", name, value),
+                name,
+                command: false,
+            });
+        }
+        self.candidates.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    // Listing shows each candidate's signature, while only its name is inserted
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context) -> Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| c.is_alphanumeric() || c == '_')
+            .last()
+            .map_or(pos, |(index, _)| index);
+        let word = &line[start..pos];
+        if word.is_empty() || word.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok((pos, Vec::new()));
+        }
+        let first_word = line[..start].trim().is_empty();
+
+        let candidates = self
+            .candidates
+            .iter()
+            .filter(|candidate| candidate.name.starts_with(word) && (first_word || !candidate.command))
+            .map(|candidate| Pair {
+                display: candidate.signature.clone(),
+                replacement: candidate.replacement.clone(),
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = Preview;
+
+    // Incomplete lines are expected while typing, so errors just hide the preview
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context) -> Option<Preview> {
+        if pos < line.len() {
+            return None;
+        }
+        let tokens = lexer(line, self.width).ok()?;
+        let statement = self.parser.parse(tokens).ok()?;
+        let value = self.evaluator.preview(&statement)?;
+
+        let mode = self.evaluator.mode();
+        let hint = match mode.parse(&mode.decimal(&value)) {
+            Some(ref pattern) if mode.signed && mode.width != Width::Unbounded => {
+                format!("{:#x} ({})", pattern, mode.decimal(pattern))
+            }
+            Some(ref pattern) => format!("{:#x} ({})", pattern, pattern),
+            None => return None,
+        };
+        Some(Preview(format!("  = {}", hint)))
+    }
+}
+
+fn error_span(error: &LexerError) -> Span {
+    match *error {
+        LexerError::UnknownOperator(span)
+        | LexerError::RadixError(span)
+        | LexerError::DecimalError(span)
+        | LexerError::HexadecimalError(span)
+        | LexerError::OctalError(span)
+        | LexerError::BinaryError(span)
+        | LexerError::SlotError(span)
+        | LexerError::OutOfRange(span, _) => span,
+    }
+}
+
+fn token_colour(token: &Token) -> Option<&'static str> {
+    match *token {
+        Token::Decimal(_) => Some(DECIMAL),
+        Token::Hexadecimal(_) => Some(HEXADECIMAL),
+        Token::Octal(_) => Some(OCTAL),
+        Token::Binary(_) => Some(BINARY),
+        Token::Operator(_) | Token::Assign | Token::Comma | Token::Colon => Some(OPERATOR),
+        Token::Keyword(_) | Token::Def => Some(KEYWORD),
+        Token::OpenBracket
+        | Token::CloseBracket
+        | Token::OpenSquareBracket
+        | Token::CloseSquareBracket => Some(BRACKET),
+        _ => None,
+    }
+}
+
+// Position of the bracket pairing with the one at `position`, if any
+fn matching_bracket(tokens: &Tokens, position: usize) -> Option<usize> {
+    let index = tokens.iter().position(|&(start, _)| start == position)?;
+    let (forward, open, close) = match tokens[index].1 {
+        Token::OpenBracket => (true, Token::OpenBracket, Token::CloseBracket),
+        Token::CloseBracket => (false, Token::CloseBracket, Token::OpenBracket),
+        Token::OpenSquareBracket => (true, Token::OpenSquareBracket, Token::CloseSquareBracket),
+        Token::CloseSquareBracket => (false, Token::CloseSquareBracket, Token::OpenSquareBracket),
+        _ => return None,
+    };
+
+    let mut depth = 0;
+    let mut scan = |&(start, ref token): &(usize, Token)| {
+        if *token == open {
+            depth += 1;
+        } else if *token == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(start);
+            }
+        }
+        None
+    };
+    if forward {
+        tokens[index..].iter().find_map(&mut scan)
+    } else {
+        tokens[..=index].iter().rev().find_map(&mut scan)
+    }
+}
+
+impl ReplHelper {
+    // Colour of every character in the line, following the lexer, with
+    // everything it rejects marked as an error
+    fn colours(&self, line: &[char], cursor: usize) -> Vec<Option<&'static str>> {
+        let mut colours = vec![None; line.len()];
+        let text: String = line.iter().collect();
+        let (tokens, errors) = scan(&text, self.width);
+
+        for &(start, ref token) in &tokens {
+            let end = line.len().min(start + token.to_string().chars().count());
+            for colour in &mut colours[start..end] {
+                *colour = token_colour(token);
+            }
+        }
+        for error in &errors {
+            let span = error_span(error);
+            for colour in &mut colours[span.start..line.len().min(span.end)] {
+                *colour = Some(ERROR);
+            }
+        }
+        // The bracket under the cursor, or just before it
+        let bracket = [cursor, cursor.wrapping_sub(1)]
+            .iter()
+            .find_map(|&index| matching_bracket(&tokens, index));
+        if let Some(position) = bracket {
+            colours[position] = Some(MATCHING_BRACKET);
+        }
+        colours
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if !self.colour || line.is_empty() {
+            return Borrowed(line);
+        }
+        let characters: Vec<char> = line.chars().collect();
+        let cursor = line[..pos].chars().count();
+        let colours = self.colours(&characters, cursor);
+
+        let mut highlighted = String::new();
+        let mut current = None;
+        for (&character, &colour) in characters.iter().zip(&colours) {
+            if colour != current {
+                if current.is_some() {
+                    highlighted.push_str("\x1b[0m");
+                }
+                if let Some(colour) = colour {
+                    highlighted.push_str(&format!("\x1b[{}m", colour));
+                }
+                current = colour;
+            }
+            highlighted.push(character);
+        }
+        if current.is_some() {
+            highlighted.push_str("\x1b[0m");
+        }
+        Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        if !self.colour {
+            return Borrowed(hint);
+        }
+        Owned(format!("\x1b[{}m{}\x1b[0m", PREVIEW, hint))
+    }
+
+    // Redraw on every change so that bracket matching follows the cursor
+    fn highlight_char(&self, _line: &str, _pos: usize, kind: CmdKind) -> bool {
+        self.colour && kind != CmdKind::ForcedRefresh
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use rustyline::completion::Completer;
+    use rustyline::highlight::Highlighter;
+    use rustyline::hint::{Hint, Hinter};
+    use rustyline::history::DefaultHistory;
+    use rustyline::Context;
+    use evaluator::Evaluator;
+    use helper::{ReplHelper, BRACKET, MATCHING_BRACKET};
+    use lexer::lexer;
+    use parser::Parser;
+
+    fn complete(helper: &ReplHelper, line: &str) -> (usize, Vec<String>) {
+        let history = DefaultHistory::new();
+        let (start, candidates) = helper.complete(line, line.len(), &Context::new(&history)).unwrap();
+        (start, candidates.into_iter().map(|pair| pair.replacement).collect())
+    }
+
+    #[test]
+    fn complete_keywords() {
+        let helper = ReplHelper::new(&Evaluator::default());
+        assert_eq!(complete(&helper, "he"), (0, vec!["help".to_string()]));
+        assert_eq!(complete(&helper, "hi"), (0, vec!["history".to_string()]));
+        // Commands only start a line
+        assert_eq!(complete(&helper, "1 + he"), (4, Vec::new()));
+    }
+
+    #[test]
+    fn complete_functions_and_variables() {
+        let parser = Parser::default();
+        let mut evaluator = Evaluator::default();
+        for line in &["pos = 3", "def popmask(v) = v & 0xff"] {
+            let statement = parser.parse(lexer(line, evaluator.mode().width).unwrap()).unwrap();
+            evaluator.evaluate(statement).unwrap();
+        }
+        let helper = ReplHelper::new(&evaluator);
+
+        let expected: Vec<String> = vec![
+            "popcount(".to_string(),
+            "popmask(".to_string(),
+            "pos".to_string(),
+        ];
+        assert_eq!(complete(&helper, "1 + po"), (4, expected));
+        assert_eq!(complete(&helper, "0x1"), (3, Vec::new()));
+    }
+
+    #[test]
+    fn signatures() {
+        let helper = ReplHelper::new(&Evaluator::default());
+        let history = DefaultHistory::new();
+        let (_, candidates) = helper.complete("GEN", 3, &Context::new(&history)).unwrap();
+        assert_eq!(candidates[0].display, "GENMASK(h, l)            bits h down to l set");
+    }
+
+    #[test]
+    fn highlight() {
+        let mut helper = ReplHelper::new(&Evaluator::default());
+        helper.colour = true;
+        let highlighted = helper.highlight("0x1f + 0b1", 10);
+        assert_eq!(
+            highlighted,
+            "\x1b[33m0x1f\x1b[0m \x1b[34m+\x1b[0m \x1b[35m0b1\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn highlight_error() {
+        let mut helper = ReplHelper::new(&Evaluator::default());
+        helper.colour = true;
+        let highlighted = helper.highlight("1 @x 2", 6);
+        assert_eq!(
+            highlighted,
+            "\x1b[32m1\x1b[0m \x1b[31m@\x1b[0mx \x1b[32m2\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn highlight_matching_bracket() {
+        let helper = ReplHelper::new(&Evaluator::default());
+        let colours = helper.colours(&"((1) + 2)".chars().collect::<Vec<char>>(), 0);
+        assert_eq!(colours[0], Some(BRACKET));
+        assert_eq!(colours[8], Some(MATCHING_BRACKET));
+        assert_eq!(colours[3], Some(BRACKET));
+
+        let colours = helper.colours(&"x[7:0]".chars().collect::<Vec<char>>(), 6);
+        assert_eq!(colours[1], Some(MATCHING_BRACKET));
+    }
+
+    fn hint(helper: &ReplHelper, line: &str) -> Option<String> {
+        let history = DefaultHistory::new();
+        helper
+            .hint(line, line.len(), &Context::new(&history))
+            .map(|preview| preview.display().to_string())
+    }
+
+    #[test]
+    fn preview() {
+        let mut evaluator = Evaluator::default();
+        let helper = ReplHelper::new(&evaluator);
+        assert_eq!(hint(&helper, "0xff << 8"), Some("  = 0xff00 (65280)".to_string()));
+        assert_eq!(hint(&helper, "0xff <<"), None);
+        assert_eq!(hint(&helper, "(1 +"), None);
+        assert_eq!(hint(&helper, "exit"), None);
+        assert_eq!(hint(&helper, "a = 4"), Some("  = 0x4 (4)".to_string()));
+        assert_eq!(hint(&helper, "a"), None);
+
+        evaluator.set_signed(true);
+        let helper = ReplHelper::new(&evaluator);
+        assert_eq!(hint(&helper, "-1"), Some("  = 0xffffffff (-1)".to_string()));
+    }
+}
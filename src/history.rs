@@ -0,0 +1,144 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub const DEFAULT_SIZE: usize = 1000;
+
+// Lines typed in earlier sessions, oldest first
+pub struct History {
+    path: Option<PathBuf>,
+    size: usize,
+    entries: Vec<String>,
+}
+
+// `$BSPL_HISTORY`, otherwise `$XDG_DATA_HOME/bspl/history`,
+// falling back to `~/.local/share/bspl/history` as XDG suggests
+pub fn default_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("BSPL_HISTORY") {
+        return Some(PathBuf::from(path));
+    }
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(data_home) => PathBuf::from(data_home),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data_home.join("bspl").join("history"))
+}
+
+impl History {
+    pub fn new(path: Option<PathBuf>, size: usize) -> History {
+        History {
+            path,
+            size,
+            entries: Vec::new(),
+        }
+    }
+
+    // A missing or unreadable file just starts an empty history
+    pub fn load(path: Option<PathBuf>, size: usize) -> History {
+        let mut history = History::new(path, size);
+        let contents = history
+            .path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        for line in contents.lines() {
+            history.add(line);
+        }
+        history
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut contents = self.entries.join("\n");
+        contents.push('\n');
+        fs::write(path, contents)
+    }
+
+    // Repeating a line moves it to the end instead of storing it twice
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != line);
+        self.entries.push(line.to_string());
+        if self.entries.len() > self.size {
+            let excess = self.entries.len() - self.size;
+            self.entries.drain(..excess);
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    // Entries containing the term, numbered from 1 like the listing
+    pub fn search(&self, term: &str) -> Vec<(usize, &String)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|&(_, entry)| entry.contains(term))
+            .map(|(index, entry)| (index + 1, entry))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use history::History;
+
+    #[test]
+    fn add_dedup() {
+        let mut history = History::new(None, 10);
+        history.add("0xff");
+        history.add("1 << 4");
+        history.add("  0xff ");
+        history.add("");
+        assert_eq!(history.entries(), ["1 << 4", "0xff"]);
+    }
+
+    #[test]
+    fn add_size() {
+        let mut history = History::new(None, 2);
+        history.add("1");
+        history.add("2");
+        history.add("3");
+        assert_eq!(history.entries(), ["2", "3"]);
+    }
+
+    #[test]
+    fn search() {
+        let mut history = History::new(None, 10);
+        history.add("mask = 0xff << 8");
+        history.add("1 + 2");
+        history.add("mask & 0xf0");
+        let found: Vec<(usize, &String)> = history.search("mask");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], (1, &"mask = 0xff << 8".to_string()));
+        assert_eq!(found[1], (3, &"mask & 0xf0".to_string()));
+    }
+
+    #[test]
+    fn save_load() {
+        let directory = env::temp_dir().join(format!("bspl-history-{}", std::process::id()));
+        let path = directory.join("nested").join("history");
+
+        let mut history = History::new(Some(path.clone()), 10);
+        history.add("0xff");
+        history.add("width 64");
+        history.save().unwrap();
+
+        let history = History::load(Some(path), 1);
+        assert_eq!(history.entries(), ["width 64"]);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,30 +1,230 @@
-use std::u32;
+use std::fmt;
+use num_bigint::BigInt;
+use ast::Span;
 use error::LexerError;
+use mode::Width;
+use constants::KEYWORDS;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Eq, Hash, PartialEq, Clone)]
 pub enum Symbol {
     OR,
     AND,
     XOR,
     NOT,
+    NEG,
     RSHIFT,
     LSHIFT,
+    ADD,
+    SUB,
+    MUL,
+    DIV,
+    MOD,
+    POW,
+    EQ,
+    NE,
+    LT,
+    LE,
+    GT,
+    GE,
+    LAND,
+    LOR,
+    LNOT,
+    ROTL,
+    ROTR,
+    INDEX,
+    SLICE,
+    Function(String),
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     OpenBracket,
     CloseBracket,
+    OpenSquareBracket,
+    CloseSquareBracket,
+    Colon,
     Keyword(String),
     Decimal(String),     // 42
     Hexadecimal(String), // 0x2a
+    Octal(String),       // 0o52
+    Binary(String),      // 0b101010
     Operator(Symbol),
+    Identifier(String),  // mask
+    LastResult,          // _
+    Slot(usize),         // $3
+    Assign,
+    Function(String),    // popcount(
+    Comma,
+    Call(String, usize), // Function with its argument count, produced by the parser
+    Assignment(String),  // Store into a variable, produced by the parser
+    Term(String),        // The rest of a `history` line, searched for as it is
+    Def,
+}
+
+impl Token {
+    // Digits and radix of a literal, without its prefix
+    fn digits(&self) -> Option<(&str, u32)> {
+        match *self {
+            Token::Decimal(ref dec) => Some((dec, 10)),
+            Token::Hexadecimal(ref hex) => Some((&hex[2..], 16)),
+            Token::Octal(ref oct) => Some((&oct[2..], 8)),
+            Token::Binary(ref bin) => Some((&bin[2..], 2)),
+            _ => None,
+        }
+    }
+
+    pub fn is_literal(&self) -> bool {
+        self.digits().is_some()
+    }
+
+    // Whether the token completes an operand, such as `42`, `mask`, `(1 + 2)` or `x[3]`
+    pub fn ends_operand(&self) -> bool {
+        match *self {
+            Token::CloseBracket
+            | Token::CloseSquareBracket
+            | Token::Identifier(_)
+            | Token::LastResult
+            | Token::Slot(_) => true,
+            _ => self.is_literal(),
+        }
+    }
+
+    pub fn value(&self) -> Option<BigInt> {
+        self.digits().and_then(|(digits, radix)| {
+            let digits: String = digits.chars().filter(|&c| c != '_').collect();
+            BigInt::parse_bytes(digits.as_bytes(), radix)
+        })
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            Symbol::OR => "|",
+            Symbol::AND => "&",
+            Symbol::XOR => "^",
+            Symbol::NOT => "~",
+            Symbol::NEG | Symbol::SUB => "-",
+            Symbol::RSHIFT => ">>",
+            Symbol::LSHIFT => "<<",
+            Symbol::ADD => "+",
+            Symbol::MUL => "*",
+            Symbol::DIV => "/",
+            Symbol::MOD => "%",
+            Symbol::POW => "**",
+            Symbol::EQ => "==",
+            Symbol::NE => "!=",
+            Symbol::LT => "<",
+            Symbol::LE => "<=",
+            Symbol::GT => ">",
+            Symbol::GE => ">=",
+            Symbol::LAND => "&&",
+            Symbol::LOR => "||",
+            Symbol::LNOT => "!",
+            Symbol::ROTL => "<<<",
+            Symbol::ROTR => ">>>",
+            Symbol::INDEX => "[]",
+            Symbol::SLICE => "[:]",
+            Symbol::Function(ref name) => name,
+        };
+        write!(f, "{}", text)
+    }
+}
+
+// Tokens print as they are typed
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::OpenBracket => write!(f, "("),
+            Token::CloseBracket => write!(f, ")"),
+            Token::OpenSquareBracket => write!(f, "["),
+            Token::CloseSquareBracket => write!(f, "]"),
+            Token::Colon => write!(f, ":"),
+            Token::Comma => write!(f, ","),
+            Token::Assign => write!(f, "="),
+            Token::LastResult => write!(f, "_"),
+            Token::Slot(slot) => write!(f, "${}", slot),
+            Token::Def => write!(f, "def"),
+            Token::Operator(ref symbol) => write!(f, "{}", symbol),
+            Token::Keyword(ref text)
+            | Token::Decimal(ref text)
+            | Token::Hexadecimal(ref text)
+            | Token::Octal(ref text)
+            | Token::Binary(ref text)
+            | Token::Identifier(ref text)
+            | Token::Function(ref text)
+            | Token::Call(ref text, _)
+            | Token::Assignment(ref text)
+            | Token::Term(ref text) => write!(f, "{}", text),
+        }
+    }
 }
 
 pub type Tokens = Vec<(usize, Token)>;
 
-pub fn lexer(line: &str) -> Result<Tokens, LexerError> {
+// Rebuild the text of lexed tokens, with single spaces wherever the input had any
+pub fn render(tokens: &[(usize, Token)]) -> String {
+    let mut text = String::new();
+    let mut end: Option<usize> = None;
+    for (position, token) in tokens {
+        if end.is_some_and(|end| *position > end) {
+            text.push(' ');
+        }
+        let token = token.to_string();
+        end = Some(position + token.chars().count());
+        text.push_str(&token);
+    }
+    text
+}
+
+// Whether the next token has a left-hand operand, telling binary minus apart from negation
+fn follows_operand(tokens: &Tokens) -> bool {
+    tokens.last().is_some_and(|(_, token)| token.ends_operand())
+}
+
+// Digits may be grouped with single underscores, but not end with one
+fn well_formed(digits: &str, radix: u32) -> bool {
+    digits.chars().any(|c| c.is_digit(radix))
+        && digits.chars().all(|c| c == '_' || c.is_digit(radix))
+        && !digits.contains("__")
+        && !digits.ends_with('_')
+}
+
+fn number(literal: String, position: usize, width: Width) -> Result<Token, LexerError> {
+    let span = Span::new(position, position + literal.chars().count());
+    let prefix = literal.get(..2).map(|prefix| prefix.to_lowercase());
+    let (token, error) = match prefix.as_deref() {
+        Some("0x") => (Token::Hexadecimal(literal), LexerError::HexadecimalError(span)),
+        Some("0o") => (Token::Octal(literal), LexerError::OctalError(span)),
+        Some("0b") => (Token::Binary(literal), LexerError::BinaryError(span)),
+        _ => (Token::Decimal(literal), LexerError::DecimalError(span)),
+    };
+
+    if !token.digits().is_some_and(|(digits, radix)| well_formed(digits, radix)) {
+        return Err(error);
+    }
+    if !width.fits(&token.value().unwrap()) {
+        return Err(LexerError::OutOfRange(span, width));
+    }
+
+    Ok(token)
+}
+
+// Stands in for a malformed number, so the rest of the line can still be parsed
+fn placeholder() -> Token {
+    Token::Decimal("0".to_string())
+}
+
+// Tokens of the line along with every error in it. Lexing carries on after
+// each error, and malformed numbers are replaced rather than dropped.
+pub fn scan(line: &str, width: Width) -> (Tokens, Vec<LexerError>) {
+    if let Some(tokens) = search(line) {
+        return (tokens, Vec::new());
+    }
+
     let mut tokens = Tokens::new();
+    let mut errors = Vec::new();
 
     let mut iterator = line.chars().enumerate().peekable();
     while let Some((position, character)) = iterator.next() {
@@ -35,72 +235,205 @@ pub fn lexer(line: &str) -> Result<Tokens, LexerError> {
         match character {
             ')' => tokens.push((position, Token::CloseBracket)),
             '(' => tokens.push((position, Token::OpenBracket)),
+            ']' => tokens.push((position, Token::CloseSquareBracket)),
+            '[' => tokens.push((position, Token::OpenSquareBracket)),
+            ',' => tokens.push((position, Token::Comma)),
+            '$' => {
+                let mut slot = String::new();
+                while let Some(&(_, digit)) = iterator.peek() {
+                    if !digit.is_ascii_digit() {
+                        break;
+                    }
+                    iterator.next();
+                    slot.push(digit);
+                }
+                match slot.parse() {
+                    Ok(slot) => tokens.push((position, Token::Slot(slot))),
+                    Err(_) => {
+                        let span = Span::new(position, position + 1 + slot.len());
+                        errors.push(LexerError::SlotError(span));
+                    }
+                }
+            }
+            ':' => tokens.push((position, Token::Colon)),
             '^' => tokens.push((position, Token::Operator(Symbol::XOR))),
-            '&' => tokens.push((position, Token::Operator(Symbol::AND))),
-            '|' => tokens.push((position, Token::Operator(Symbol::OR))),
+            '&' => match iterator.peek() {
+                Some(&(_, '&')) => {
+                    iterator.next();
+                    tokens.push((position, Token::Operator(Symbol::LAND)));
+                }
+                _ => tokens.push((position, Token::Operator(Symbol::AND))),
+            },
+            '|' => match iterator.peek() {
+                Some(&(_, '|')) => {
+                    iterator.next();
+                    tokens.push((position, Token::Operator(Symbol::LOR)));
+                }
+                _ => tokens.push((position, Token::Operator(Symbol::OR))),
+            },
+            '!' => match iterator.peek() {
+                Some(&(_, '=')) => {
+                    iterator.next();
+                    tokens.push((position, Token::Operator(Symbol::NE)));
+                }
+                _ => tokens.push((position, Token::Operator(Symbol::LNOT))),
+            },
+            '=' => match iterator.peek() {
+                Some(&(_, '=')) => {
+                    iterator.next();
+                    tokens.push((position, Token::Operator(Symbol::EQ)));
+                }
+                _ => tokens.push((position, Token::Assign)),
+            },
             '~' => tokens.push((position, Token::Operator(Symbol::NOT))),
-            '>' => {
-                let shift_position = position;
-                match iterator.peek() {
-                    Some(&(_, '>')) => {
-                        iterator.next();
-                        tokens.push((shift_position, Token::Operator(Symbol::RSHIFT)));
-                    }
-                    _ => return Err(LexerError::UnknownOperator(position)),
+            '+' => tokens.push((position, Token::Operator(Symbol::ADD))),
+            '/' => tokens.push((position, Token::Operator(Symbol::DIV))),
+            '%' => tokens.push((position, Token::Operator(Symbol::MOD))),
+            '-' => {
+                if follows_operand(&tokens) {
+                    tokens.push((position, Token::Operator(Symbol::SUB)));
+                } else {
+                    tokens.push((position, Token::Operator(Symbol::NEG)));
                 }
             }
-            '<' => {
-                let shift_position = position;
-                match iterator.peek() {
-                    Some(&(_, '<')) => {
-                        iterator.next();
-                        tokens.push((shift_position, Token::Operator(Symbol::LSHIFT)));
+            '*' => match iterator.peek() {
+                Some(&(_, '*')) => {
+                    iterator.next();
+                    tokens.push((position, Token::Operator(Symbol::POW)));
+                }
+                _ => tokens.push((position, Token::Operator(Symbol::MUL))),
+            },
+            '>' => match iterator.peek() {
+                Some(&(_, '>')) => {
+                    iterator.next();
+                    match iterator.peek() {
+                        Some(&(_, '>')) => {
+                            iterator.next();
+                            tokens.push((position, Token::Operator(Symbol::ROTR)));
+                        }
+                        _ => tokens.push((position, Token::Operator(Symbol::RSHIFT))),
                     }
-                    _ => return Err(LexerError::UnknownOperator(position)),
                 }
-            }
+                Some(&(_, '=')) => {
+                    iterator.next();
+                    tokens.push((position, Token::Operator(Symbol::GE)));
+                }
+                _ => tokens.push((position, Token::Operator(Symbol::GT))),
+            },
+            '<' => match iterator.peek() {
+                Some(&(_, '<')) => {
+                    iterator.next();
+                    match iterator.peek() {
+                        Some(&(_, '<')) => {
+                            iterator.next();
+                            tokens.push((position, Token::Operator(Symbol::ROTL)));
+                        }
+                        _ => tokens.push((position, Token::Operator(Symbol::LSHIFT))),
+                    }
+                }
+                Some(&(_, '=')) => {
+                    iterator.next();
+                    tokens.push((position, Token::Operator(Symbol::LE)));
+                }
+                _ => tokens.push((position, Token::Operator(Symbol::LT))),
+            },
 
-            _ if character.is_alphanumeric() => {
+            _ if character.is_alphanumeric() || character == '_' => {
                 let radix_position = position;
                 let mut radix = String::new();
                 radix.push(character);
                 while let Some(&(_, rx)) = iterator.peek() {
-                    if !rx.is_alphanumeric() {
+                    if !rx.is_alphanumeric() && rx != '_' {
                         break;
                     }
                     iterator.next();
                     radix.push(rx);
                 }
 
-                if radix.parse::<u32>().is_ok() {
-                    tokens.push((radix_position, Token::Decimal(radix)));
-                } else if radix.as_str().starts_with("0x")
-                    && u32::from_str_radix(radix.as_str().split_at(2).1, 16).is_ok()
+                if character.is_ascii_digit() {
+                    match number(radix, radix_position, width) {
+                        Ok(token) => tokens.push((radix_position, token)),
+                        Err(error) => {
+                            errors.push(error);
+                            tokens.push((radix_position, placeholder()));
+                        }
+                    }
+                } else if iterator
+                    .clone()
+                    .find(|&(_, c)| !c.is_whitespace())
+                    .is_some_and(|(_, c)| c == '(')
                 {
-                    tokens.push((radix_position, Token::Hexadecimal(radix)));
-                } else if radix.chars().all(|c| c.is_alphabetic()) {
-                    tokens.push((radix_position, Token::Keyword(radix)));
+                    tokens.push((radix_position, Token::Function(radix)));
+                } else if radix == "_" {
+                    tokens.push((radix_position, Token::LastResult));
+                } else if character.is_alphabetic() || character == '_' {
+                    tokens.push((radix_position, Token::Identifier(radix)));
                 } else {
-                    return Err(LexerError::RadixError(position));
+                    let end = radix_position + radix.chars().count();
+                    errors.push(LexerError::RadixError(Span::new(radix_position, end)));
+                    tokens.push((radix_position, placeholder()));
                 }
             }
-            _ => return Err(LexerError::UnknownOperator(position)),
+            _ => errors.push(LexerError::UnknownOperator(Span::new(position, position + 1))),
         }
     }
 
-    Ok(tokens)
+    // A command stands alone, optionally followed by a single literal argument,
+    // anywhere else the same word is a variable
+    let command = match tokens.as_slice() {
+        [(_, Token::Identifier(word))] => Some(word),
+        [(_, Token::Identifier(word)), (_, argument)] if argument.is_literal() => Some(word),
+        _ => None,
+    };
+    if let Some(word) = command.filter(|word| KEYWORDS.contains(&&*word.to_lowercase())) {
+        tokens[0].1 = Token::Keyword(word.clone());
+    }
+
+    // Likewise `def` only starts a definition when a function name follows
+    if let [(_, Token::Identifier(ref word)), (_, Token::Function(_)), ..] = tokens[..] {
+        if word == "def" {
+            tokens[0].1 = Token::Def;
+        }
+    }
+
+    (tokens, errors)
+}
+
+// `history` takes everything after it as the term to search for, so that
+// `history x & 0xff` searches instead of reading a variable
+fn search(line: &str) -> Option<Tokens> {
+    let start = line.chars().take_while(|c| c.is_whitespace()).count();
+    let word: String = line.chars().skip(start).take_while(|c| !c.is_whitespace()).collect();
+    if word.to_lowercase() != "history" {
+        return None;
+    }
+    let end = start + word.chars().count();
+    let gap = line.chars().skip(end).take_while(|c| c.is_whitespace()).count();
+    let term: String = line.chars().skip(end + gap).collect();
+    Some(vec![
+        (start, Token::Keyword(word)),
+        (end + gap, Token::Term(term.trim_end().to_string())),
+    ])
+}
+
+pub fn lexer(line: &str, width: Width) -> Result<Tokens, Vec<LexerError>> {
+    match scan(line, width) {
+        (tokens, ref errors) if errors.is_empty() => Ok(tokens),
+        (_, errors) => Err(errors),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::u32;
+    use ast::Span;
     use error::LexerError;
-    use lexer::{lexer, Symbol, Token, Tokens};
+    use lexer::{lexer, render, scan, Symbol, Token, Tokens};
+    use mode::Width;
 
     #[test]
     fn blank() {
         let expression = "";
-        assert_eq!(lexer(expression).unwrap(), vec![]);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), vec![]);
     }
 
     #[test]
@@ -112,7 +445,21 @@ mod tests {
             (2, Token::Operator(Symbol::OR)),
             (3, Token::Operator(Symbol::NOT)),
         ];
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn negative() {
+        let expression = "-1 & ~-0x10";
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::NEG)),
+            (1, Token::Decimal("1".to_string())),
+            (3, Token::Operator(Symbol::AND)),
+            (5, Token::Operator(Symbol::NOT)),
+            (6, Token::Operator(Symbol::NEG)),
+            (7, Token::Hexadecimal("0x10".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
@@ -127,7 +474,7 @@ mod tests {
             (11, Token::Decimal("12".to_string())),
             (13, Token::CloseBracket),
         ];
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
@@ -141,76 +488,284 @@ mod tests {
             (10, Token::Decimal("12".to_string())),
             (12, Token::CloseBracket),
         ];
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
     fn keywords_multiple() {
         let expression = "exit help license bspl";
         let tokens: Tokens = vec![
-            (0, Token::Keyword("exit".to_string())),
-            (5, Token::Keyword("help".to_string())),
-            (10, Token::Keyword("license".to_string())),
-            (18, Token::Keyword("bspl".to_string())),
+            (0, Token::Identifier("exit".to_string())),
+            (5, Token::Identifier("help".to_string())),
+            (10, Token::Identifier("license".to_string())),
+            (18, Token::Identifier("bspl".to_string())),
         ];
 
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn function_call() {
+        let expression = "popcount(0xff, 2) + log2 (8)";
+        let tokens: Tokens = vec![
+            (0, Token::Function("popcount".to_string())),
+            (8, Token::OpenBracket),
+            (9, Token::Hexadecimal("0xff".to_string())),
+            (13, Token::Comma),
+            (15, Token::Decimal("2".to_string())),
+            (16, Token::CloseBracket),
+            (18, Token::Operator(Symbol::ADD)),
+            (20, Token::Function("log2".to_string())),
+            (25, Token::OpenBracket),
+            (26, Token::Decimal("8".to_string())),
+            (27, Token::CloseBracket),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
     fn symbol_invalid() {
-        let expression = "^&%|~";
-        assert_eq!(lexer(expression), Err(LexerError::UnknownOperator(2)));
+        let expression = "^&@|~";
+        assert_eq!(
+            lexer(expression, Width::W32),
+            Err(vec![LexerError::UnknownOperator(Span::new(2, 3))])
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        let expression = "+-*/%**";
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::ADD)),
+            (1, Token::Operator(Symbol::NEG)),
+            (2, Token::Operator(Symbol::MUL)),
+            (3, Token::Operator(Symbol::DIV)),
+            (4, Token::Operator(Symbol::MOD)),
+            (5, Token::Operator(Symbol::POW)),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn minus_binary_and_unary() {
+        let expression = "(1) - -2 - 3";
+        let tokens: Tokens = vec![
+            (0, Token::OpenBracket),
+            (1, Token::Decimal("1".to_string())),
+            (2, Token::CloseBracket),
+            (4, Token::Operator(Symbol::SUB)),
+            (6, Token::Operator(Symbol::NEG)),
+            (7, Token::Decimal("2".to_string())),
+            (9, Token::Operator(Symbol::SUB)),
+            (11, Token::Decimal("3".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
     fn decimal_max() {
         let expression = "4294967295";
         let tokens: Tokens = vec![(0, Token::Decimal(u32::MAX.to_string()))];
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
     fn decimal_overflow() {
         let expression = "4294967296";
-        assert_eq!(lexer(expression), Err(LexerError::RadixError(0)));
+        assert_eq!(
+            lexer(expression, Width::W32),
+            Err(vec![LexerError::OutOfRange(Span::new(0, 10), Width::W32)])
+        );
     }
 
     #[test]
     fn hexadecimal_max() {
         let expression = "0xffffffff";
         let tokens: Tokens = vec![(0, Token::Hexadecimal("0xffffffff".to_string()))];
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
     fn hexadecimal_overflow() {
         let expression = "0xabcdefgh";
-        assert_eq!(lexer(expression), Err(LexerError::RadixError(0)));
+        assert_eq!(
+            lexer(expression, Width::W32),
+            Err(vec![LexerError::HexadecimalError(Span::new(0, 10))])
+        );
+    }
+
+    #[test]
+    fn decimal_max_width() {
+        let expression = "255 18446744073709551615";
+        assert_eq!(
+            lexer(expression, Width::W8),
+            Err(vec![LexerError::OutOfRange(Span::new(4, 24), Width::W8)])
+        );
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("255".to_string())),
+            (4, Token::Decimal(u64::MAX.to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W64).unwrap(), tokens);
+    }
+
+    #[test]
+    fn unbounded() {
+        let expression = "0x100000000000000000000000000000000 340282366920938463463374607431768211456";
+        let tokens: Tokens = vec![
+            (0, Token::Hexadecimal("0x100000000000000000000000000000000".to_string())),
+            (36, Token::Decimal("340282366920938463463374607431768211456".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::Unbounded).unwrap(), tokens);
     }
 
     #[test]
-    fn rshift_incomplete() {
+    fn hexadecimal_overflow_width() {
+        let expression = "0x100";
+        assert_eq!(
+            lexer(expression, Width::W8),
+            Err(vec![LexerError::OutOfRange(Span::new(0, 5), Width::W8)])
+        );
+        let expression = "0x100000000000000000000000000000000";
+        assert_eq!(
+            lexer(expression, Width::W128),
+            Err(vec![LexerError::OutOfRange(Span::new(0, 35), Width::W128)])
+        );
+    }
+
+    #[test]
+    fn rshift_greater() {
         let expression = ">> > << ";
-        assert_eq!(lexer(expression), Err(LexerError::UnknownOperator(3)));
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::RSHIFT)),
+            (3, Token::Operator(Symbol::GT)),
+            (5, Token::Operator(Symbol::LSHIFT)),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
     fn rshift_multiple() {
         let expression = ">>>> >> >> ";
         let tokens: Tokens = vec![
-            (0, Token::Operator(Symbol::RSHIFT)),
-            (2, Token::Operator(Symbol::RSHIFT)),
+            (0, Token::Operator(Symbol::ROTR)),
+            (3, Token::Operator(Symbol::GT)),
             (5, Token::Operator(Symbol::RSHIFT)),
             (8, Token::Operator(Symbol::RSHIFT)),
         ];
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
-    fn lshift_incomplete() {
+    fn lshift_less() {
         let expression = "< <<";
-        assert_eq!(lexer(expression), Err(LexerError::UnknownOperator(0)));
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::LT)),
+            (2, Token::Operator(Symbol::LSHIFT)),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn comparisons() {
+        let expression = "== != < <= > >=";
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::EQ)),
+            (3, Token::Operator(Symbol::NE)),
+            (6, Token::Operator(Symbol::LT)),
+            (8, Token::Operator(Symbol::LE)),
+            (11, Token::Operator(Symbol::GT)),
+            (13, Token::Operator(Symbol::GE)),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn assign() {
+        let expression = "mask_1 = 0xff";
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("mask_1".to_string())),
+            (7, Token::Assign),
+            (9, Token::Hexadecimal("0xff".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn keyword_standalone() {
+        let tokens: Tokens = vec![
+            (0, Token::Keyword("width".to_string())),
+            (6, Token::Decimal("64".to_string())),
+        ];
+        assert_eq!(lexer("width 64", Width::W32).unwrap(), tokens);
+
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("help".to_string())),
+            (5, Token::Operator(Symbol::ADD)),
+            (7, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(lexer("help + 1", Width::W32).unwrap(), tokens);
+
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("help".to_string())),
+            (5, Token::Assign),
+            (7, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(lexer("help = 1", Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn history_term() {
+        let tokens: Tokens = vec![
+            (0, Token::Keyword("history".to_string())),
+            (7, Token::Term("".to_string())),
+        ];
+        assert_eq!(lexer("history", Width::W32).unwrap(), tokens);
+
+        // Everything after the keyword is searched for, even if it does not lex
+        let tokens: Tokens = vec![
+            (1, Token::Keyword("history".to_string())),
+            (10, Token::Term("x & 0xff @".to_string())),
+        ];
+        assert_eq!(lexer(" history  x & 0xff @ ", Width::W32).unwrap(), tokens);
+
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("historyx".to_string())),
+            (9, Token::Operator(Symbol::ADD)),
+            (11, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(lexer("historyx + 1", Width::W32).unwrap(), tokens);
+
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::ADD)),
+            (4, Token::Identifier("history".to_string())),
+        ];
+        assert_eq!(lexer("1 + history", Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn identifier_minus() {
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("mask".to_string())),
+            (5, Token::Operator(Symbol::SUB)),
+            (7, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(lexer("mask - 1", Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn logical() {
+        let expression = "!a && b || & |";
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::LNOT)),
+            (1, Token::Identifier("a".to_string())),
+            (3, Token::Operator(Symbol::LAND)),
+            (6, Token::Identifier("b".to_string())),
+            (8, Token::Operator(Symbol::LOR)),
+            (11, Token::Operator(Symbol::AND)),
+            (13, Token::Operator(Symbol::OR)),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
@@ -218,11 +773,24 @@ mod tests {
         let expression = "<< <<<< <<";
         let tokens: Tokens = vec![
             (0, Token::Operator(Symbol::LSHIFT)),
-            (3, Token::Operator(Symbol::LSHIFT)),
-            (5, Token::Operator(Symbol::LSHIFT)),
+            (3, Token::Operator(Symbol::ROTL)),
+            (6, Token::Operator(Symbol::LT)),
             (8, Token::Operator(Symbol::LSHIFT)),
         ];
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn rotate() {
+        let expression = "1 <<< 4 >>> 2";
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::ROTL)),
+            (6, Token::Decimal("4".to_string())),
+            (8, Token::Operator(Symbol::ROTR)),
+            (12, Token::Decimal("2".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
@@ -237,7 +805,7 @@ mod tests {
             (6, Token::OpenBracket),
             (7, Token::CloseBracket),
         ];
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
     #[test]
@@ -251,7 +819,176 @@ mod tests {
             (4, Token::CloseBracket),
             (5, Token::CloseBracket),
         ];
-        assert_eq!(lexer(expression).unwrap(), tokens);
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
     }
 
+    #[test]
+    fn binary_octal() {
+        let expression = "0b1010 | 0o755";
+        let tokens: Tokens = vec![
+            (0, Token::Binary("0b1010".to_string())),
+            (7, Token::Operator(Symbol::OR)),
+            (9, Token::Octal("0o755".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn uppercase_prefixes() {
+        let expression = "0XFF 0B11 0O17";
+        let tokens: Tokens = vec![
+            (0, Token::Hexadecimal("0XFF".to_string())),
+            (5, Token::Binary("0B11".to_string())),
+            (10, Token::Octal("0O17".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn separators() {
+        let expression = "0b1010_0000 1_000_000 0x_dead_beef";
+        let tokens: Tokens = vec![
+            (0, Token::Binary("0b1010_0000".to_string())),
+            (12, Token::Decimal("1_000_000".to_string())),
+            (22, Token::Hexadecimal("0x_dead_beef".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+        assert_eq!(tokens[0].1.value().unwrap(), 160.into());
+        assert_eq!(tokens[1].1.value().unwrap(), 1_000_000.into());
+        assert_eq!(tokens[2].1.value().unwrap(), 0xdead_beef_u32.into());
+    }
+
+    #[test]
+    fn separators_invalid() {
+        assert_eq!(
+            lexer("1__0", Width::W32),
+            Err(vec![LexerError::DecimalError(Span::new(0, 4))])
+        );
+        assert_eq!(
+            lexer("0b1_", Width::W32),
+            Err(vec![LexerError::BinaryError(Span::new(0, 4))])
+        );
+        assert_eq!(
+            lexer("0x_", Width::W32),
+            Err(vec![LexerError::HexadecimalError(Span::new(0, 3))])
+        );
+    }
+
+    #[test]
+    fn binary_invalid() {
+        let expression = "1 | 0b102";
+        assert_eq!(
+            lexer(expression, Width::W32),
+            Err(vec![LexerError::BinaryError(Span::new(4, 9))])
+        );
+    }
+
+    #[test]
+    fn octal_invalid() {
+        let expression = "0o78";
+        assert_eq!(
+            lexer(expression, Width::W32),
+            Err(vec![LexerError::OctalError(Span::new(0, 4))])
+        );
+    }
+
+    #[test]
+    fn decimal_invalid() {
+        let expression = "12ab";
+        assert_eq!(
+            lexer(expression, Width::W32),
+            Err(vec![LexerError::DecimalError(Span::new(0, 4))])
+        );
+    }
+
+    #[test]
+    fn binary_overflow() {
+        let expression = "0b1_0000_0000";
+        assert_eq!(
+            lexer(expression, Width::W8),
+            Err(vec![LexerError::OutOfRange(Span::new(0, 13), Width::W8)])
+        );
+    }
+
+    #[test]
+    fn slice() {
+        let result: Tokens = vec![
+            (0, Token::Hexadecimal("0xff".to_string())),
+            (4, Token::OpenSquareBracket),
+            (5, Token::Decimal("7".to_string())),
+            (6, Token::Colon),
+            (7, Token::Decimal("4".to_string())),
+            (8, Token::CloseSquareBracket),
+            (10, Token::Operator(Symbol::SUB)),
+            (12, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(lexer("0xff[7:4] - 1", Width::W32).unwrap(), result);
+    }
+
+    #[test]
+    fn slots() {
+        let expression = "_ + $12 - _x";
+        let tokens: Tokens = vec![
+            (0, Token::LastResult),
+            (2, Token::Operator(Symbol::ADD)),
+            (4, Token::Slot(12)),
+            (8, Token::Operator(Symbol::SUB)),
+            (10, Token::Identifier("_x".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn scan_recovery() {
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("0".to_string())),
+            (5, Token::Operator(Symbol::ADD)),
+            (7, Token::Decimal("1".to_string())),
+            (11, Token::Decimal("2".to_string())),
+        ];
+        let errors = vec![
+            LexerError::HexadecimalError(Span::new(0, 4)),
+            LexerError::UnknownOperator(Span::new(9, 10)),
+        ];
+        assert_eq!(scan("0xfg + 1 @ 2", Width::W32), (tokens, errors));
+    }
+
+    #[test]
+    fn slot_invalid() {
+        assert_eq!(lexer("$x", Width::W32), Err(vec![LexerError::SlotError(Span::new(0, 1))]));
+        assert_eq!(lexer("1 + $", Width::W32), Err(vec![LexerError::SlotError(Span::new(4, 5))]));
+    }
+
+    #[test]
+    fn definition() {
+        let expression = "def align_up(x, a) = x + 1";
+        let tokens: Tokens = vec![
+            (0, Token::Def),
+            (4, Token::Function("align_up".to_string())),
+            (12, Token::OpenBracket),
+            (13, Token::Identifier("x".to_string())),
+            (14, Token::Comma),
+            (16, Token::Identifier("a".to_string())),
+            (17, Token::CloseBracket),
+            (19, Token::Assign),
+            (21, Token::Identifier("x".to_string())),
+            (23, Token::Operator(Symbol::ADD)),
+            (25, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(lexer(expression, Width::W32).unwrap(), tokens);
+
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("def".to_string())),
+            (4, Token::Operator(Symbol::ADD)),
+            (6, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(lexer("def + 1", Width::W32).unwrap(), tokens);
+    }
+
+    #[test]
+    fn render_spacing() {
+        let expression = "(x+a  - 1) & ~(a-1)[3:0]";
+        let tokens = lexer(expression, Width::W32).unwrap();
+        assert_eq!(render(&tokens), "(x+a - 1) & ~(a-1)[3:0]");
+    }
 }
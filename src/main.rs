@@ -1,23 +1,156 @@
+extern crate num_bigint;
+extern crate num_integer;
+extern crate num_traits;
 extern crate rustyline;
-extern crate tempfile;
+extern crate unicode_width;
 
+mod ast;
 mod lexer;
 mod parser;
 mod evaluator;
 mod error;
 mod constants;
 mod function;
+mod mode;
+mod history;
+mod helper;
+mod script;
+mod program;
+mod diagnostic;
 
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
-use tempfile::NamedTempFile;
+use rustyline::history::DefaultHistory;
+use rustyline::{CompletionType, Config, Editor};
 use constants::VERSION;
-use lexer::lexer;
+use ast::{Span, Statement};
+use lexer::{lexer, scan};
 use parser::Parser;
 use evaluator::Evaluator;
 use error::{EvaluatorError, LexerError, ParserError};
+use mode::{Mode, Width, MAX_UNBOUNDED_BITS};
+use history::History;
+use helper::ReplHelper;
+use diagnostic::{underline, Diagnostic};
 
 const PROMPT: &str = "=> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+// Errors shown for one line, any more are only counted
+const MAX_ERRORS: usize = 5;
+const USAGE: &str = "Usage: bspl [-w|--width 8|16|32|64|128] [-s|--signed] [-b|--big]
+            [--history FILE] [--history-size N] [--history-skip-errors]
+       bspl [options] [-f|--format dec|hex|oct|bin] -e EXPRESSION | EXPRESSION... | FILE
+       bspl [options] [-f|--format dec|hex|oct|bin] --map EXPRESSION < NUMBERS";
+
+// The radix used to print a single value outside the REPL
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Decimal,
+    Hexadecimal,
+    Octal,
+    Binary,
+}
+
+impl Format {
+    fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "dec" => Some(Format::Decimal),
+            "hex" => Some(Format::Hexadecimal),
+            "oct" => Some(Format::Octal),
+            "bin" => Some(Format::Binary),
+            _ => None,
+        }
+    }
+}
+
+struct Options {
+    mode: Mode,
+    expressions: Vec<String>,
+    script: Option<PathBuf>,
+    map: Option<String>,
+    format: Option<Format>,
+    history: Option<PathBuf>,
+    history_size: usize,
+    history_skip_errors: bool,
+}
+
+fn options<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        mode: Mode::default(),
+        expressions: Vec::new(),
+        script: None,
+        map: None,
+        format: None,
+        history: history::default_path(),
+        history_size: history::DEFAULT_SIZE,
+        history_skip_errors: false,
+    };
+
+    // Anything that is not an option is part of a single expression, so
+    // `bspl 0xff00 '&' 0x0ff0` needs no quoting around the whole thing
+    let mut words = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-e" | "--expression" => {
+                options
+                    .expressions
+                    .push(args.next().ok_or_else(|| format!("{} expects an expression", arg))?);
+            }
+            "--map" => {
+                options.map = Some(args.next().ok_or_else(|| format!("{} expects an expression", arg))?);
+            }
+            "-f" | "--format" => {
+                options.format = Some(
+                    args.next()
+                        .and_then(|name| Format::from_name(&name))
+                        .ok_or_else(|| format!("{} expects one of dec, hex, oct or bin", arg))?,
+                );
+            }
+            "--" => words.extend(args.by_ref()),
+            "-w" | "--width" => {
+                options.mode.width = args
+                    .next()
+                    .and_then(|bits| bits.parse::<BigInt>().ok())
+                    .and_then(|bits| Width::from_bits(&bits))
+                    .ok_or_else(|| format!("{} expects one of 8, 16, 32, 64 or 128", arg))?;
+            }
+            "-s" | "--signed" => options.mode.signed = true,
+            "-b" | "--big" => options.mode.width = Width::Unbounded,
+            "--history" => {
+                options.history = Some(PathBuf::from(
+                    args.next().ok_or_else(|| format!("{} expects a file", arg))?,
+                ));
+            }
+            "--history-size" => {
+                options.history_size = args
+                    .next()
+                    .and_then(|size| size.parse().ok())
+                    .ok_or_else(|| format!("{} expects a number of lines", arg))?;
+            }
+            "--history-skip-errors" => options.history_skip_errors = true,
+            // Once the expression has started, `bspl 1 -1` subtracts
+            _ if arg.starts_with('-') && arg.len() > 1 && words.is_empty() => {
+                return Err(format!("Unknown argument '{}'", arg));
+            }
+            _ => words.push(arg),
+        }
+    }
+    // A lone argument naming a file is a script, which is how a shebang runs it
+    if words.len() == 1 && Path::new(&words[0]).is_file() {
+        options.script = words.pop().map(PathBuf::from);
+    } else if !words.is_empty() {
+        options.expressions.push(words.join(" "));
+    }
+
+    Ok(options)
+}
 
 fn prelude() {
     println!("bspl {}", VERSION);
@@ -25,79 +158,302 @@ fn prelude() {
     println!("Type 'help', 'license', or 'version' for more information.");
 }
 
-fn error_message(position: usize, msg: &str) {
-    println!(
-        "{caret:>width$}\n.. {}",
-        msg,
-        caret = "^",
-        width = PROMPT.len() + 1 + position
-    );
+// The count of errors left out, if there are too many to show
+fn hidden(diagnostics: &[Diagnostic]) -> Option<String> {
+    match diagnostics.len().saturating_sub(MAX_ERRORS) {
+        0 => None,
+        1 => Some("1 more error not shown".to_string()),
+        more => Some(format!("{} more errors not shown", more)),
+    }
+}
+
+// Underline each error in the input, which may span several lines. Only the
+// first error can sit right below its line, so every other error and note
+// shows its line again, as do earlier lines that have scrolled out of reach.
+fn error_message(input: &str, diagnostics: &[Diagnostic]) {
+    for line in error_lines(input, diagnostics) {
+        println!("{}", line);
+    }
+}
+
+// Carets go beneath the line of the input they point at, after the prompt it
+// was typed on. The line is shown again unless it is the last one typed and
+// nothing has been printed below it yet.
+fn error_lines(input: &str, diagnostics: &[Diagnostic]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let last = input.split('\n').count() - 1;
+    for diagnostic in diagnostics.iter().take(MAX_ERRORS) {
+        for (index, (span, msg)) in diagnostic.labels().into_iter().enumerate() {
+            let (line, text, carets) = underline(input, span);
+            if !lines.is_empty() || line < last {
+                let prompt = if line == 0 { PROMPT } else { CONTINUATION_PROMPT };
+                lines.push(format!("{}{}", prompt, text));
+            }
+            let kind = if index > 0 { "Note: " } else { "" };
+            lines.push(format!("{:indent$}{}", "", carets, indent = PROMPT.len()));
+            lines.push(format!(".. {}{}", kind, msg));
+        }
+    }
+    if let Some(hidden) = hidden(diagnostics) {
+        lines.push(format!(".. {}", hidden));
+    }
+    lines
+}
+
+// Whether the input needs another line, because brackets are still open or
+// it ends in a backslash. The backslash is replaced with a space, which keeps
+// the columns of the remaining characters unchanged.
+fn continues(input: &mut String, parser: &Parser, width: Width) -> bool {
+    let trimmed = input.trim_end().len();
+    if input[..trimmed].ends_with('\\') {
+        input.truncate(trimmed - 1);
+        input.push(' ');
+        return true;
+    }
+    match lexer(input, width).map(|tokens| parser.parse(tokens)) {
+        Ok(Err(errors)) => errors
+            .iter()
+            .any(|error| matches!(*error, ParserError::MissingClosingBracket(_))),
+        _ => false,
+    }
+}
+
+// Read a line, then keep reading on a continuation prompt while it continues
+fn read_input(
+    repl: &mut Editor<ReplHelper, DefaultHistory>,
+    parser: &Parser,
+    width: Width,
+) -> Result<String, ReadlineError> {
+    let mut input = repl.readline(PROMPT)?;
+    while continues(&mut input, parser, width) {
+        input.push('\n');
+        input.push_str(&repl.readline(CONTINUATION_PROMPT)?);
+    }
+    Ok(input)
 }
 
-fn display_results(results: Vec<String>) {
+fn lexer_error(err: LexerError) -> Diagnostic {
+    let (span, msg) = match err {
+        LexerError::RadixError(span) => (span, "Not a valid number or keyword"),
+        LexerError::DecimalError(span) => (span, "Not a valid decimal literal"),
+        LexerError::HexadecimalError(span) => (span, "Not a valid hexadecimal literal"),
+        LexerError::OctalError(span) => (span, "Not a valid octal literal"),
+        LexerError::BinaryError(span) => (span, "Not a valid binary literal"),
+        LexerError::SlotError(span) => (span, "Not a valid result slot"),
+        LexerError::UnknownOperator(span) => (span, "Not a valid operator"),
+        LexerError::OutOfRange(span, width) => {
+            return Diagnostic::new(span, format!("Literal does not fit in {} width", width));
+        }
+    };
+    Diagnostic::new(span, msg)
+}
+
+// A missing closing bracket is reported at the end of the input
+fn parser_error(err: ParserError, input: &str) -> Diagnostic {
+    let (span, msg) = match err {
+        ParserError::MissingOpeningBracket(span) => (span, "Missing an opening bracket"),
+        ParserError::MissingClosingBracket(open) => {
+            let end = input.chars().count();
+            return Diagnostic::new(Span::new(end, end + 1), "Missing a closing bracket")
+                .note(open, "Opening bracket was here");
+        }
+        ParserError::KeywordError(span) => (span, "Cannot use keyword in expression"),
+        ParserError::MisplacedComma(span) => (span, "Comma outside of a function call"),
+        ParserError::MisplacedColon(span) => (span, "Colon outside of a bit slice"),
+        ParserError::MisplacedSlice(span) => (span, "Bit slice without a value to slice"),
+        ParserError::EmptySlice(span) => (span, "Missing a bit position"),
+        ParserError::MisplacedAssignment(span) => {
+            (span, "Can only assign an expression to a variable")
+        }
+        ParserError::DefinitionError(span) => (span, "Not a valid function definition"),
+        ParserError::MissingArgument(span) => (span, "Missing argument from expression"),
+        ParserError::TooManyArguments(span) => (span, "Too many arguments in expression"),
+        ParserError::TooDeep(span) => (span, "Expression is nested too deeply"),
+    };
+    Diagnostic::new(span, msg)
+}
+
+fn evaluator_error(err: EvaluatorError, mode: Mode) -> Diagnostic {
+    let (span, msg) = match err {
+        EvaluatorError::MissingArgument(span) => (span, "Missing argument from expression"),
+        EvaluatorError::TooManyArguments(span) => (span, "Too many arguments in expression"),
+        EvaluatorError::OverflowShift(span) => (span, "Shift overflow error"),
+        EvaluatorError::NegativeShift(span) => (span, "Negative shift count"),
+        EvaluatorError::DivisionByZero(span) => (span, "Division by zero"),
+        EvaluatorError::Overflow(span) => {
+            return Diagnostic::new(span, format!("Result does not fit in {}", mode));
+        }
+        EvaluatorError::TooLarge(span) => {
+            return Diagnostic::new(span, format!("Result exceeds {} bits", MAX_UNBOUNDED_BITS));
+        }
+        EvaluatorError::NegativeExponent(span) => (span, "Negative exponent"),
+        EvaluatorError::FixedWidthRequired(span) => (span, "Operation requires a fixed width"),
+        EvaluatorError::UnknownVariable(span) => (span, "Not a defined variable"),
+        EvaluatorError::UnknownSlot(span) => (span, "No result in that slot"),
+        EvaluatorError::Recursion(span) => (span, "Function would call itself"),
+        EvaluatorError::ReservedName(span) => (span, "Cannot redefine a builtin function"),
+        EvaluatorError::UnknownFunction(span) => (span, "Not a valid function"),
+        EvaluatorError::Domain(span) => (span, "Argument outside the function's domain"),
+        EvaluatorError::BitOutOfRange(span) => (span, "Bit position outside the width"),
+        EvaluatorError::ReversedBounds(span) => (span, "High bit is below the low bit"),
+        EvaluatorError::UnknownKeyword(span) => (span, "Not a valid keyword"),
+        EvaluatorError::InvalidArgument(span) => (span, "Not a valid argument for keyword"),
+        EvaluatorError::PromptOnly(span) => (span, "Only available at the prompt"),
+        EvaluatorError::TooDeep(span) => (span, "Expression is nested too deeply"),
+        EvaluatorError::Exit => (Span::new(0, 0), "exit"),
+    };
+    Diagnostic::new(span, msg)
+}
+
+// Lex and parse one line, with every error either of them finds in order
+fn parse(line: &str, parser: &Parser, width: Width) -> Result<Statement, Vec<Diagnostic>> {
+    let (tokens, errors) = scan(line, width);
+    // Without the characters the lexer dropped, the parser can only guess at
+    // the shape of the line, so it is only asked when numbers were replaced
+    let dropped = errors
+        .iter()
+        .any(|error| matches!(*error, LexerError::UnknownOperator(_) | LexerError::SlotError(_)));
+    let mut diagnostics: Vec<Diagnostic> = errors.into_iter().map(lexer_error).collect();
+    if !dropped {
+        match parser.parse(tokens) {
+            Ok(statement) if diagnostics.is_empty() => return Ok(statement),
+            Ok(_) => {}
+            Err(errors) => {
+                diagnostics.extend(errors.into_iter().map(|err| parser_error(err, line)));
+            }
+        }
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    Err(diagnostics)
+}
+
+// Lex, parse and evaluate one line. `Ok(None)` means the line asked to exit.
+fn run(
+    line: &str,
+    parser: &Parser,
+    evaluator: &mut Evaluator,
+) -> Result<Option<Vec<String>>, Vec<Diagnostic>> {
+    let statement = parse(line, parser, evaluator.mode().width)?;
+    execute(statement, evaluator)
+}
+
+fn execute(
+    statement: Statement,
+    evaluator: &mut Evaluator,
+) -> Result<Option<Vec<String>>, Vec<Diagnostic>> {
+    match evaluator.evaluate(statement) {
+        Ok(result) => Ok(Some(result)),
+        Err(EvaluatorError::Exit) => Ok(None),
+        Err(err) => Err(vec![evaluator_error(err, evaluator.mode())]),
+    }
+}
+
+fn display_value(value: &BigInt, mode: Mode) {
+    println!("D: {}", value);
+    if mode.signed && mode.width != Width::Unbounded {
+        println!("S: {}", mode.width.sign_extend(value));
+    }
+    println!("H: {:#x}", value);
+    println!("B: {:#b}", value);
+}
+
+fn formatted(value: &BigInt, mode: Mode, format: Format) -> String {
+    match format {
+        Format::Decimal if mode.signed => mode.width.sign_extend(value).to_string(),
+        Format::Decimal => value.to_string(),
+        Format::Hexadecimal => format!("{:#x}", value),
+        Format::Octal => format!("{:#o}", value),
+        Format::Binary => format!("{:#b}", value),
+    }
+}
+
+fn display_results(results: Vec<String>, mode: Mode, slot: Option<usize>) {
     if let Some((final_result, results)) = results.split_last() {
         for result in results {
             println!(".. {}", result);
         }
 
-        if let Ok(parsed_final_result) = final_result.parse::<u32>() {
-            println!("D: {}", parsed_final_result);
-            println!("H: {:#x}", parsed_final_result);
-            println!("B: {:#b}", parsed_final_result);
+        if let Some(parsed_final_result) = mode.parse(final_result) {
+            if let Some(slot) = slot {
+                println!("${}", slot);
+            }
+            display_value(&parsed_final_result, mode);
         } else {
             println!(".. {}", final_result);
         }
     }
 }
 
-fn repl() {
-    let mut repl = Editor::<()>::new();
-    let tmp_file = NamedTempFile::new().unwrap();
-    let _ = repl.load_history(tmp_file.path());
+fn list_history(history: &History, term: &str) {
+    let entries = history.search(term);
+    if entries.is_empty() {
+        println!(".. No matching history");
+    }
+    for (number, entry) in entries {
+        println!("{:>5}  {}", number, entry);
+    }
+}
 
+fn repl(options: Options) {
     let parser = Parser::default();
-    let evaluator = Evaluator::default();
+    let mut evaluator = Evaluator::default();
+    evaluator.set_width(options.mode.width);
+    evaluator.set_signed(options.mode.signed);
+
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .max_history_size(options.history_size)
+        .and_then(|config| config.history_ignore_dups(true))
+        .map(|config| config.build());
+    let mut repl = match config.and_then(Editor::<ReplHelper, DefaultHistory>::with_config) {
+        Ok(repl) => repl,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    };
+    repl.set_helper(Some(ReplHelper::new(&evaluator)));
+
+    let mut history = History::load(options.history, options.history_size);
+    for entry in history.entries() {
+        let _ = repl.add_history_entry(entry.as_str());
+    }
+    let mut slots = evaluator.slots();
 
     loop {
-        match repl.readline(PROMPT) {
+        match read_input(&mut repl, &parser, evaluator.mode().width) {
             Ok(line) => {
-                repl.add_history_entry(&line);
-                match lexer(&line) {
-                    Ok(tokens) => match parser.parse(tokens) {
-                        Ok(parsed_tokens) => match evaluator.evaluate(parsed_tokens) {
-                            Ok(result) => {
-                                display_results(result);
-                            }
-                            Err(EvaluatorError::MissingArgument(position)) => {
-                                error_message(position, "Missing argument from expression");
-                            }
-                            Err(EvaluatorError::TooManyArguments) => {
-                                error_message(0, "Too many arguments in expression");
-                            }
-                            Err(EvaluatorError::OverflowShift(position)) => {
-                                error_message(position, "Shift overflow error");
-                            }
-                            Err(EvaluatorError::UnknownKeyword(position)) => {
-                                error_message(position, "Not a valid keyword");
-                            }
-                            Err(EvaluatorError::Exit) => break,
-                        },
-                        Err(ParserError::MissingOpeningBracket(position)) => {
-                            error_message(position, "Missing an opening bracket");
-                        }
-                        Err(ParserError::MissingClosingBracket(position)) => {
-                            error_message(position, "Missing a closing bracket");
-                        }
-                        Err(ParserError::KeywordError(position)) => {
-                            error_message(position, "Cannot use keyword in expression");
-                        }
-                    },
-                    Err(LexerError::RadixError(position)) => {
-                        error_message(position, "Not a valid decimal, hexadecimal, or keyword");
+                let entry = line.replace('\n', " ");
+                let _ = repl.add_history_entry(entry.as_str());
+                let statement = parse(&line, &parser, evaluator.mode().width);
+                if statement.is_ok() || !options.history_skip_errors {
+                    history.add(&entry);
+                    if let Err(err) = history.save() {
+                        println!("Error: could not save history: {}", err);
                     }
-                    Err(LexerError::UnknownOperator(position)) => {
-                        error_message(position, "Not a valid operator");
+                }
+
+                // The REPL answers `history` itself, everything else is evaluated
+                let outcome = match statement {
+                    Ok(Statement::History(_, ref term)) => {
+                        list_history(&history, term);
+                        continue;
+                    }
+                    Ok(statement) => execute(statement, &mut evaluator),
+                    Err(diagnostics) => Err(diagnostics),
+                };
+                match outcome {
+                    Ok(Some(result)) => {
+                        // Only expressions take a slot, commands leave the count alone
+                        let slot = Some(evaluator.slots()).filter(|&slot| slot > slots);
+                        slots = evaluator.slots();
+                        display_results(result, evaluator.mode(), slot);
+                        if let Some(helper) = repl.helper_mut() {
+                            helper.refresh(&evaluator);
+                        }
                     }
+                    Ok(None) => break,
+                    Err(diagnostics) => error_message(&line, &diagnostics),
                 }
             }
             Err(ReadlineError::Eof) => break,
@@ -109,11 +465,346 @@ fn repl() {
                 break;
             }
         }
-        repl.save_history(tmp_file.path()).unwrap();
     }
 }
 
+// Show the input underlined beneath each error and note, for output that
+// has no prompt
+fn report_error(input: &str, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics.iter().take(MAX_ERRORS) {
+        for (index, (span, msg)) in diagnostic.labels().into_iter().enumerate() {
+            let (_, text, carets) = underline(input, span);
+            let kind = if index > 0 { "Note" } else { "Error" };
+            eprintln!("{}\n{}\n{}: {}", text, carets, kind, msg);
+        }
+    }
+    if let Some(hidden) = hidden(diagnostics) {
+        eprintln!("{}", hidden);
+    }
+}
+
+// The final value alone, without the steps taken to reach it
+fn display_final(results: &[String], mode: Mode, format: Option<Format>) {
+    match results.last().map(|last| (last, mode.parse(last))) {
+        Some((_, Some(value))) => match format {
+            Some(format) => println!("{}", formatted(&value, mode, format)),
+            None => display_value(&value, mode),
+        },
+        Some((last, None)) => println!("{}", last),
+        None => {}
+    }
+}
+
+// Evaluate the expressions given on the command line in order, printing only
+// the final value of each. The exit status is 1 if any of them fails.
+fn evaluate_arguments(options: Options) -> i32 {
+    let parser = Parser::default();
+    let mut evaluator = Evaluator::default();
+    evaluator.set_width(options.mode.width);
+    evaluator.set_signed(options.mode.signed);
+
+    for expression in &options.expressions {
+        match run(expression, &parser, &mut evaluator) {
+            Ok(Some(result)) => display_final(&result, evaluator.mode(), options.format),
+            Ok(None) => break,
+            Err(diagnostics) => {
+                report_error(expression, &diagnostics);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+// Run each statement of a script in turn, stopping at the first error
+fn run_script(path: &Path, options: &Options) -> i32 {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return 1;
+        }
+    };
+
+    let parser = Parser::default();
+    let mut evaluator = Evaluator::default();
+    evaluator.set_width(options.mode.width);
+    evaluator.set_signed(options.mode.signed);
+
+    for statement in script::statements(&source) {
+        match run(&statement.text, &parser, &mut evaluator) {
+            Ok(Some(result)) => display_final(&result, evaluator.mode(), options.format),
+            Ok(None) => break,
+            Err(diagnostics) => {
+                // Spans count from the start of the statement, not of its line
+                let line = source.lines().nth(statement.line - 1).unwrap_or("");
+                for diagnostic in diagnostics.iter().take(MAX_ERRORS) {
+                    for (index, (span, msg)) in diagnostic.labels().into_iter().enumerate() {
+                        let (start, end) = (statement.column + span.start, statement.column + span.end);
+                        let (_, text, carets) = underline(line, Span::new(start, end));
+                        let kind = if index > 0 { "note: " } else { "" };
+                        let location = format!("{}:{}:{}", path.display(), statement.line, start + 1);
+                        eprintln!("{}: {}{}\n{}\n{}", location, kind, msg, text, carets);
+                    }
+                }
+                if let Some(hidden) = hidden(&diagnostics) {
+                    eprintln!("{}:{}: {}", path.display(), statement.line, hidden);
+                }
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+// A number read by `--map`, which may be negative in signed and big modes
+fn input_value(input: &str, mode: Mode) -> Result<BigInt, Diagnostic> {
+    let (negative, digits) = match input.strip_prefix('-') {
+        Some(digits) if mode.signed || mode.width == Width::Unbounded => (true, digits),
+        _ => (false, input),
+    };
+    let value = match lexer(digits, mode.width) {
+        Ok(ref tokens) if tokens.len() == 1 && tokens[0].1.is_literal() => tokens[0].1.value(),
+        Ok(_) => None,
+        // A number is a single token, so its first error says enough
+        Err(mut errors) => return Err(lexer_error(errors.remove(0))),
+    };
+    let span = Span::new(0, input.chars().count());
+    match value {
+        Some(value) if negative && !mode.contains(&-&value) => {
+            Err(lexer_error(LexerError::OutOfRange(span, mode.width)))
+        }
+        Some(value) if negative => Ok(mode.width.mask(-value)),
+        Some(value) => Ok(value),
+        None => Err(Diagnostic::new(span, "Not a number")),
+    }
+}
+
+// Apply an expression to every number read from stdin, with the number bound
+// to `x`. Bad lines are reported and skipped, but still fail the exit status.
+fn map_input(expression: &str, options: &Options) -> i32 {
+    let parser = Parser::default();
+    let mut evaluator = Evaluator::default();
+    evaluator.set_width(options.mode.width);
+    evaluator.set_signed(options.mode.signed);
+    let width = evaluator.mode().width;
+
+    let expr = match parse(expression, &parser, width) {
+        Ok(Statement::Expression(expr)) => Ok(expr),
+        Ok(Statement::Empty) => {
+            Err(vec![Diagnostic::new(Span::new(0, 0), "Missing argument from expression")])
+        }
+        Ok(Statement::Command(_, span, _))
+        | Ok(Statement::History(span, _))
+        | Ok(Statement::Definition(_, span, _)) => {
+            Err(vec![Diagnostic::new(span, "Can only map an expression")])
+        }
+        Err(diagnostics) => Err(diagnostics),
+    };
+    // A mistake that fails every line is reported once, before reading any
+    let expr = expr.and_then(|expr| match evaluator.check(&expr, &["x"]) {
+        Ok(()) => Ok(expr),
+        Err(err) => Err(vec![evaluator_error(err, evaluator.mode())]),
+    });
+    let expr = match expr {
+        Ok(expr) => expr,
+        Err(diagnostics) => {
+            report_error(expression, &diagnostics);
+            return 1;
+        }
+    };
+
+    // The compiled program is much faster, the evaluator covers the rest
+    let mut program = evaluator.compile(&expr, "x");
+    let format = options.format.unwrap_or(Format::Decimal);
+    let mut status = 0;
+    let stdin = io::stdin();
+    for (number, line) in stdin.lock().lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("stdin:{}: {}", number + 1, err);
+                status = 1;
+                continue;
+            }
+        };
+        let input = line.trim();
+        if input.is_empty() {
+            continue;
+        }
+
+        let value = match input_value(input, evaluator.mode()) {
+            Ok(value) => value,
+            Err(diagnostic) => {
+                eprintln!("stdin:{}: {}", number + 1, diagnostic.message);
+                status = 1;
+                continue;
+            }
+        };
+
+        let result = match (program.as_mut(), value.to_u128()) {
+            (Some(program), Some(input)) => program.run(input).map(BigInt::from),
+            _ => evaluator.apply(&expr, &iter::once(("x".to_string(), value)).collect()),
+        };
+        match result {
+            Ok(result) => println!("{}", formatted(&result, evaluator.mode(), format)),
+            Err(err) => {
+                eprintln!("stdin:{}: {}", number + 1, evaluator_error(err, evaluator.mode()).message);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
 pub fn main() {
+    let options = match options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{}\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Some(ref expression) = options.map {
+        process::exit(map_input(expression, &options));
+    }
+    if let Some(ref path) = options.script {
+        process::exit(run_script(path, &options));
+    }
+    if !options.expressions.is_empty() {
+        process::exit(evaluate_arguments(options));
+    }
     prelude();
-    repl();
+    repl(options);
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use evaluator::Evaluator;
+    use mode::{Mode, Width};
+    use parser::Parser;
+    use {continues, error_lines, formatted, input_value, options, parse, run, Format};
+
+    fn args(words: &[&str]) -> impl Iterator<Item = String> {
+        words.iter().map(|word| word.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn continue_open_bracket() {
+        let parser = Parser::default();
+        let mut input = "(1 + (2".to_string();
+        assert!(continues(&mut input, &parser, Width::W32));
+        assert_eq!(input, "(1 + (2");
+
+        let mut input = "(1 + (2\n))".to_string();
+        assert!(!continues(&mut input, &parser, Width::W32));
+
+        // Too many closing brackets can never be fixed by reading more
+        let mut input = "1 + 2)".to_string();
+        assert!(!continues(&mut input, &parser, Width::W32));
+    }
+
+    #[test]
+    fn continue_backslash() {
+        let parser = Parser::default();
+        let mut input = "0xff00 & \\ ".to_string();
+        assert!(continues(&mut input, &parser, Width::W32));
+        assert_eq!(input, "0xff00 &  ");
+
+        let mut input = "0xff00 & 0x0ff0".to_string();
+        assert!(!continues(&mut input, &parser, Width::W32));
+    }
+
+    #[test]
+    fn error_on_second_line() {
+        let parser = Parser::default();
+        // The line with the error was just typed, so only the carets are needed
+        let input = "(1 +\n2 @ 3)";
+        let diagnostics = parse(input, &parser, Width::W32).unwrap_err();
+        assert_eq!(error_lines(input, &diagnostics), ["     ^", ".. Not a valid operator"]);
+
+        // An earlier line is shown again after its prompt
+        let input = "(1 @\n2)";
+        let diagnostics = parse(input, &parser, Width::W32).unwrap_err();
+        assert_eq!(
+            error_lines(input, &diagnostics),
+            ["=> (1 @", "      ^", ".. Not a valid operator"]
+        );
+
+        let input = "(1 +\n2 +\n3";
+        let diagnostics = parse(input, &parser, Width::W32).unwrap_err();
+        assert_eq!(
+            error_lines(input, &diagnostics),
+            [
+                "    ^",
+                ".. Missing a closing bracket",
+                "=> (1 +",
+                "   ^",
+                ".. Note: Opening bracket was here",
+            ]
+        );
+    }
+
+    #[test]
+    fn arguments_joined() {
+        // The shell would otherwise treat & as its own operator
+        let parsed = options(args(&["-w", "16", "0xff00", "&", "0x0ff0"])).unwrap();
+        assert_eq!(parsed.expressions, ["0xff00 & 0x0ff0"]);
+        assert_eq!(parsed.mode.width, Width::W16);
+
+        let mut evaluator = Evaluator::default();
+        let result = run(&parsed.expressions[0], &Parser::default(), &mut evaluator).unwrap();
+        assert_eq!(result.unwrap().last().unwrap(), "3840");
+
+        // A leading minus is an option, but later ones belong to the expression
+        let parsed = options(args(&["-e", "-1", "1", "-1"])).unwrap();
+        assert_eq!(parsed.expressions, ["-1", "1 -1"]);
+        assert_eq!(
+            options(args(&["-1"])).err(),
+            Some("Unknown argument '-1'".to_string())
+        );
+    }
+
+    #[test]
+    fn format_selection() {
+        assert_eq!(options(args(&["1"])).unwrap().format, None);
+        for &(name, format) in &[
+            ("dec", Format::Decimal),
+            ("hex", Format::Hexadecimal),
+            ("oct", Format::Octal),
+            ("bin", Format::Binary),
+        ] {
+            assert_eq!(options(args(&["--format", name, "1"])).unwrap().format, Some(format));
+        }
+        assert_eq!(
+            options(args(&["-f", "hexadecimal", "1"])).err(),
+            Some("-f expects one of dec, hex, oct or bin".to_string())
+        );
+
+        let value = BigInt::from(0xfe);
+        let unsigned = Mode { width: Width::W8, signed: false };
+        let signed = Mode { width: Width::W8, signed: true };
+        assert_eq!(formatted(&value, unsigned, Format::Decimal), "254");
+        assert_eq!(formatted(&value, signed, Format::Decimal), "-2");
+        assert_eq!(formatted(&value, signed, Format::Hexadecimal), "0xfe");
+        assert_eq!(formatted(&value, unsigned, Format::Octal), "0o376");
+        assert_eq!(formatted(&value, unsigned, Format::Binary), "0b11111110");
+    }
+
+    #[test]
+    fn negative_input() {
+        let signed = Mode { width: Width::W8, signed: true };
+        let unsigned = Mode { width: Width::W8, signed: false };
+        let big = Mode { width: Width::Unbounded, signed: false };
+        assert_eq!(input_value("-16", signed), Ok(BigInt::from(0xf0)));
+        assert_eq!(input_value("-0x80", signed), Ok(BigInt::from(0x80)));
+        assert_eq!(input_value("-16", big), Ok(BigInt::from(-16)));
+
+        let message = |input, mode| input_value(input, mode).unwrap_err().message;
+        assert_eq!(message("-129", signed), "Literal does not fit in 8-bit width");
+        assert_eq!(message("-16", unsigned), "Not a number");
+        assert_eq!(message("--16", signed), "Not a number");
+    }
 }
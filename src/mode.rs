@@ -0,0 +1,209 @@
+use std::fmt;
+use num_bigint::{BigInt, Sign};
+use num_traits::{One, ToPrimitive};
+
+// Largest result allowed without a fixed width, so that a typo like
+// 1 << 10**12 is reported instead of exhausting memory
+pub const MAX_UNBOUNDED_BITS: u64 = 1 << 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Width {
+    W8,
+    W16,
+    #[default]
+    W32,
+    W64,
+    W128,
+    Unbounded,
+}
+
+impl Width {
+    pub fn from_bits(bits: &BigInt) -> Option<Width> {
+        match bits.to_u32() {
+            Some(8) => Some(Width::W8),
+            Some(16) => Some(Width::W16),
+            Some(32) => Some(Width::W32),
+            Some(64) => Some(Width::W64),
+            Some(128) => Some(Width::W128),
+            _ => None,
+        }
+    }
+
+    // Number of bits in the width, None when values may grow without limit
+    pub fn bits(self) -> Option<u32> {
+        match self {
+            Width::W8 => Some(8),
+            Width::W16 => Some(16),
+            Width::W32 => Some(32),
+            Width::W64 => Some(64),
+            Width::W128 => Some(128),
+            Width::Unbounded => None,
+        }
+    }
+
+    // Largest unsigned value that fits in the width
+    pub fn max(self) -> Option<BigInt> {
+        self.bits().map(|bits| (BigInt::one() << bits) - 1)
+    }
+
+    // Truncate a value to the width, discarding any higher bits
+    pub fn mask(self, value: BigInt) -> BigInt {
+        match self.max() {
+            Some(max) => value & max,
+            None => value,
+        }
+    }
+
+    pub fn fits(self, value: &BigInt) -> bool {
+        match self.max() {
+            Some(max) => *value <= max,
+            None => true,
+        }
+    }
+
+    // Reinterpret the bit pattern of a value as a two's complement integer
+    pub fn sign_extend(self, value: &BigInt) -> BigInt {
+        match self.bits() {
+            Some(bits) if value.bit(u64::from(bits - 1)) => value - (BigInt::one() << bits),
+            _ => value.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Width {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bits() {
+            Some(bits) => write!(f, "{}-bit", bits),
+            None => write!(f, "unbounded"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Mode {
+    pub width: Width,
+    pub signed: bool,
+}
+
+impl Mode {
+    // Numeric value of a bit pattern, negative in signed mode when the top bit is set
+    pub fn value(self, value: &BigInt) -> BigInt {
+        if self.signed {
+            self.width.sign_extend(value)
+        } else {
+            value.clone()
+        }
+    }
+
+    // Whether a numeric value can be represented without wrapping around
+    pub fn contains(self, value: &BigInt) -> bool {
+        match self.width.bits() {
+            Some(bits) if self.signed => {
+                let limit = BigInt::one() << (bits - 1);
+                -&limit <= *value && *value < limit
+            }
+            Some(_) => value.sign() != Sign::Minus && self.width.fits(value),
+            None => true,
+        }
+    }
+
+    pub fn decimal(self, value: &BigInt) -> String {
+        self.value(value).to_string()
+    }
+
+    // Recover the bit pattern from a string produced by `decimal`
+    pub fn parse(self, decimal: &str) -> Option<BigInt> {
+        decimal
+            .parse::<BigInt>()
+            .ok()
+            .map(|value| self.width.mask(value))
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.width == Width::Unbounded {
+            return write!(f, "arbitrary-precision");
+        }
+        let signedness = if self.signed { "signed" } else { "unsigned" };
+        write!(f, "{} {}", signedness, self.width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use mode::{Mode, Width};
+
+    #[test]
+    fn from_bits() {
+        assert_eq!(Width::from_bits(&BigInt::from(8)), Some(Width::W8));
+        assert_eq!(Width::from_bits(&BigInt::from(128)), Some(Width::W128));
+        assert_eq!(Width::from_bits(&BigInt::from(12)), None);
+    }
+
+    #[test]
+    fn max() {
+        assert_eq!(Width::W8.max(), Some(BigInt::from(0xff)));
+        assert_eq!(Width::W32.max(), Some(BigInt::from(u32::MAX)));
+        assert_eq!(Width::W128.max(), Some(BigInt::from(u128::MAX)));
+        assert_eq!(Width::Unbounded.max(), None);
+    }
+
+    #[test]
+    fn mask() {
+        assert_eq!(Width::W8.mask(BigInt::from(0x1ff)), BigInt::from(0xff));
+        assert_eq!(Width::W16.mask(BigInt::from(0xdead_beef_u32)), BigInt::from(0xbeef));
+        assert_eq!(Width::W8.mask(BigInt::from(-1)), BigInt::from(0xff));
+        assert_eq!(Width::Unbounded.mask(BigInt::from(-1)), BigInt::from(-1));
+    }
+
+    #[test]
+    fn sign_extend() {
+        assert_eq!(Width::W8.sign_extend(&BigInt::from(0xff)), BigInt::from(-1));
+        assert_eq!(Width::W8.sign_extend(&BigInt::from(0x7f)), BigInt::from(127));
+        assert_eq!(Width::W16.sign_extend(&BigInt::from(0x8000)), BigInt::from(-32768));
+        assert_eq!(Width::W128.sign_extend(&BigInt::from(u128::MAX)), BigInt::from(-1));
+    }
+
+    #[test]
+    fn contains() {
+        let mode = Mode {
+            width: Width::W8,
+            signed: true,
+        };
+        assert!(mode.contains(&BigInt::from(-128)));
+        assert!(!mode.contains(&BigInt::from(128)));
+
+        let mode = Mode {
+            width: Width::W8,
+            signed: false,
+        };
+        assert!(mode.contains(&BigInt::from(255)));
+        assert!(!mode.contains(&BigInt::from(-1)));
+    }
+
+    #[test]
+    fn decimal_round_trip() {
+        let mode = Mode {
+            width: Width::W8,
+            signed: true,
+        };
+        assert_eq!(mode.decimal(&BigInt::from(0xfe)), "-2");
+        assert_eq!(mode.parse("-2"), Some(BigInt::from(0xfe)));
+
+        let mode = Mode {
+            width: Width::W8,
+            signed: false,
+        };
+        assert_eq!(mode.decimal(&BigInt::from(0xfe)), "254");
+        assert_eq!(mode.parse("254"), Some(BigInt::from(0xfe)));
+
+        let mode = Mode {
+            width: Width::Unbounded,
+            signed: false,
+        };
+        assert_eq!(mode.decimal(&BigInt::from(-2)), "-2");
+        assert_eq!(mode.parse("-2"), Some(BigInt::from(-2)));
+    }
+}
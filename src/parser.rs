@@ -1,6 +1,8 @@
 use std::collections::HashMap;
-use lexer::{Symbol, Token, Tokens};
+use ast::{self, Span, Statement};
+use lexer::{render, Symbol, Token, Tokens};
 use error::ParserError;
+use function::Definition;
 
 type Operators = HashMap<Symbol, Operator>;
 
@@ -32,69 +34,275 @@ impl Parser {
 
     fn lower_precedence(&self, new_token: &Token, top_token: &Token) -> bool {
         let &Operator(new_token_prec, ref new_token_assoc) = match *new_token {
-            Token::Operator(ref new_token_name) => self.operators.get(&new_token_name).unwrap(),
+            Token::Operator(ref new_token_name) => self.operators.get(new_token_name).unwrap(),
             _ => unreachable!(),
         };
 
         let &Operator(top_token_prec, _) = match *top_token {
-            Token::Operator(ref top_token_name) => self.operators.get(&top_token_name).unwrap(),
+            Token::Operator(ref top_token_name) => self.operators.get(top_token_name).unwrap(),
             _ => unreachable!(),
         };
 
-        (*new_token_assoc == Associativity::LeftToRight && new_token_prec > top_token_prec)
-            || (*new_token_assoc == Associativity::RightToLeft && new_token_prec >= top_token_prec)
+        (*new_token_assoc == Associativity::LeftToRight && new_token_prec >= top_token_prec)
+            || (*new_token_assoc == Associativity::RightToLeft && new_token_prec > top_token_prec)
     }
 
-    pub fn parse(&self, tokens: Tokens) -> Result<Tokens, ParserError> {
+    // `def name(params) = body`, with the body kept as a parsed expression
+    fn definition(
+        &self,
+        position: usize,
+        name: &str,
+        tokens: &[(usize, Token)],
+        errors: &mut Vec<ParserError>,
+    ) -> Result<Statement, ParserError> {
+        let span = Span::new(position, position + name.chars().count());
+        let open = Span::of(tokens[0].0, &tokens[0].1);
+        let mut params: Vec<String> = Vec::new();
+        // The lexer only marks a function name when an opening bracket follows
+        let mut token_iterator = tokens[1..].iter();
+        loop {
+            match token_iterator.next() {
+                Some(&(_, Token::CloseBracket)) if params.is_empty() => break,
+                Some(&(param_position, Token::Identifier(ref param))) => {
+                    if params.contains(param) {
+                        let span = Span::new(param_position, param_position + param.chars().count());
+                        return Err(ParserError::DefinitionError(span));
+                    }
+                    params.push(param.clone());
+                    match token_iterator.next() {
+                        Some(&(_, Token::Comma)) => {}
+                        Some(&(_, Token::CloseBracket)) => break,
+                        Some(&(position, ref token)) => {
+                            return Err(ParserError::DefinitionError(Span::of(position, token)))
+                        }
+                        None => return Err(ParserError::MissingClosingBracket(open)),
+                    }
+                }
+                Some(&(position, ref token)) => {
+                    return Err(ParserError::DefinitionError(Span::of(position, token)))
+                }
+                None => return Err(ParserError::MissingClosingBracket(open)),
+            }
+        }
+
+        let body = match *token_iterator.as_slice() {
+            [(_, Token::Assign), ref body @ ..] if !body.is_empty() => body,
+            [(position, ref token), ..] => {
+                return Err(ParserError::DefinitionError(Span::of(position, token)))
+            }
+            [] => return Err(ParserError::DefinitionError(span)),
+        };
+        // Definitions may not assign to variables
+        if let Some(&(position, ref token)) = body.iter().find(|(_, token)| *token == Token::Assign) {
+            return Err(ParserError::MisplacedAssignment(Span::of(position, token)));
+        }
+
+        let definition = Definition {
+            params,
+            body: ast::expression(&self.shunt(body.to_vec(), errors)?)?,
+            text: render(body),
+        };
+        Ok(Statement::Definition(name.to_string(), span, definition))
+    }
+
+    // Every error in the line, as far as parsing can recover from them
+    pub fn parse(&self, tokens: Tokens) -> Result<Statement, Vec<ParserError>> {
+        let mut errors = Vec::new();
+        let statement = if let [(_, Token::Def), (position, Token::Function(ref name)), ref definition @ ..] =
+            tokens[..]
+        {
+            self.definition(position, name, definition, &mut errors)
+        } else {
+            self.shunt(tokens, &mut errors).and_then(|output| ast::statement(&output))
+        };
+        finish(statement, errors)
+    }
+
+    // Reorder the tokens of a line into postfix with the shunting-yard
+    // algorithm, which `ast` then folds into a tree. Only tests look at the
+    // postfix on its own.
+    #[cfg(test)]
+    pub fn postfix(&self, tokens: Tokens) -> Result<Tokens, Vec<ParserError>> {
+        let mut errors = Vec::new();
+        let output = self.shunt(tokens, &mut errors);
+        finish(output, errors)
+    }
+
+    // A bracket left open is closed where the error is found and a stray
+    // closing bracket is skipped, so that both only add to `errors` and the
+    // rest of the line is still checked. Any other error ends the parse.
+    fn shunt(&self, tokens: Tokens, errors: &mut Vec<ParserError>) -> Result<Tokens, ParserError> {
+        // An assignment stores the value of the expression to its right
+        if let [(position, Token::Identifier(ref name)), (assign_position, Token::Assign), ref expression @ ..] = tokens[..] {
+            if expression.is_empty() {
+                return Err(ParserError::MisplacedAssignment(Span::of(assign_position, &Token::Assign)));
+            }
+            let mut output = self.shunt(expression.to_vec(), errors)?;
+            output.push((position, Token::Assignment(name.clone())));
+            return Ok(output);
+        }
+
         let mut stack = Tokens::new();
         let mut output = Tokens::new();
+        // Argument count for every open bracket, None unless it starts a function call
+        let mut arguments: Vec<Option<usize>> = Vec::new();
+        let mut previous: Option<&Token> = None;
+        // Stands in as the previous token once a bracket is closed by recovery
+        let closed = Token::CloseBracket;
 
         let mut token_iterator = tokens.iter().peekable();
         while let Some(&(position, ref token)) = token_iterator.next() {
             match *token {
-                Token::Decimal(_) | Token::Hexadecimal(_) => output.push((position, token.clone())),
+                Token::Decimal(_) | Token::Hexadecimal(_) | Token::Octal(_) | Token::Binary(_) => {
+                    output.push((position, token.clone()))
+                }
+                Token::Identifier(_) | Token::LastResult | Token::Slot(_) => {
+                    output.push((position, token.clone()))
+                }
+                Token::Assign => return Err(ParserError::MisplacedAssignment(Span::of(position, token))),
+                Token::Def => return Err(ParserError::DefinitionError(Span::of(position, token))),
                 Token::Keyword(_) => {
-                    if tokens.len() > 1 {
-                        return Err(ParserError::KeywordError(position));
+                    // A keyword stands alone, optionally followed by a single literal
+                    // argument or, for `history`, the term to search for
+                    let standalone = output.is_empty() && stack.is_empty();
+                    match token_iterator.next() {
+                        None if standalone => output.push((position, token.clone())),
+                        Some(argument)
+                            if standalone
+                                && (argument.1.is_literal() || matches!(argument.1, Token::Term(_)))
+                                && token_iterator.peek().is_none() =>
+                        {
+                            output.push((position, token.clone()));
+                            output.push(argument.clone());
+                        }
+                        _ => return Err(ParserError::KeywordError(Span::of(position, token))),
                     }
-                    output.push((position, token.clone()));
                     break;
                 }
-                Token::Operator(_) => {
+                Token::Operator(ref symbol) => {
+                    // A prefix operator comes before its operand, so none of
+                    // the operators waiting on the stack can take it yet
+                    while let Some(&(_, ref top_token @ Token::Operator(_))) = stack.last() {
+                        if ast::arity(symbol) != 1 && self.lower_precedence(token, top_token) {
+                            output.push(stack.pop().unwrap());
+                        } else {
+                            break;
+                        }
+                    }
+
+                    stack.push((position, token.clone()));
+                }
+                Token::Function(_) => {
+                    stack.push((position, token.clone()));
+                }
+                Token::OpenBracket => {
+                    match stack.last() {
+                        Some(&(_, Token::Function(_))) => arguments.push(Some(1)),
+                        _ => arguments.push(None),
+                    }
+                    stack.push((position, token.clone()));
+                }
+                Token::Comma => {
                     loop {
                         match stack.last() {
-                            Some(&(_, Token::Operator(_))) => {
-                                if self.lower_precedence(&token, &stack.last().unwrap().1) {
-                                    output.push(stack.pop().unwrap());
-                                } else {
-                                    break;
-                                }
+                            Some(&(_, Token::OpenBracket)) => break,
+                            Some(&(_, Token::OpenSquareBracket)) | None => {
+                                return Err(ParserError::MisplacedComma(Span::of(position, token)))
                             }
-                            _ => break,
+                            Some(_) => output.push(stack.pop().unwrap()),
                         }
                     }
 
-                    stack.push((position, token.clone()));
+                    match arguments.last_mut() {
+                        Some(&mut Some(ref mut count)) => *count += 1,
+                        _ => return Err(ParserError::MisplacedComma(Span::of(position, token))),
+                    }
                 }
-                Token::OpenBracket => {
+                Token::OpenSquareBracket => {
+                    // The slice is postfix, so its operand is already complete in the output
+                    if !previous.is_some_and(|token| token.ends_operand()) {
+                        return Err(ParserError::MisplacedSlice(Span::of(position, token)));
+                    }
+                    arguments.push(Some(1));
                     stack.push((position, token.clone()));
                 }
-                Token::CloseBracket => loop {
-                    match stack.last() {
-                        Some(&(_, Token::OpenBracket)) => {
-                            stack.pop();
-                            break;
+                Token::Colon => {
+                    if previous == Some(&Token::OpenSquareBracket) {
+                        return Err(ParserError::EmptySlice(Span::of(position, token)));
+                    }
+                    loop {
+                        match stack.last() {
+                            Some(&(_, Token::OpenSquareBracket)) => break,
+                            Some(&(_, Token::OpenBracket)) | None => {
+                                return Err(ParserError::MisplacedColon(Span::of(position, token)))
+                            }
+                            Some(_) => output.push(stack.pop().unwrap()),
+                        }
+                    }
+
+                    match arguments.last_mut() {
+                        Some(&mut Some(ref mut count)) if *count == 1 => *count += 1,
+                        _ => return Err(ParserError::MisplacedColon(Span::of(position, token))),
+                    }
+                }
+                Token::CloseSquareBracket => {
+                    loop {
+                        match stack.last() {
+                            Some(&(_, Token::OpenSquareBracket)) => break,
+                            Some(&(open_position, ref open @ Token::OpenBracket)) => {
+                                let open = Span::of(open_position, open);
+                                errors.push(ParserError::MissingClosingBracket(open));
+                                let empty = previous == Some(&Token::OpenBracket);
+                                close(&mut stack, &mut output, &mut arguments, empty);
+                                previous = Some(&closed);
+                            }
+                            Some(_) => output.push(stack.pop().unwrap()),
+                            None => break,
+                        }
+                    }
+                    if stack.is_empty() {
+                        errors.push(ParserError::MissingOpeningBracket(Span::of(position, token)));
+                        continue;
+                    }
+                    if previous == Some(&Token::OpenSquareBracket) || previous == Some(&Token::Colon) {
+                        return Err(ParserError::EmptySlice(Span::of(position, token)));
+                    }
+                    close(&mut stack, &mut output, &mut arguments, false);
+                }
+                Token::CloseBracket => {
+                    loop {
+                        match stack.last() {
+                            Some(&(_, Token::OpenBracket)) => break,
+                            Some(&(open_position, ref open @ Token::OpenSquareBracket)) => {
+                                let open = Span::of(open_position, open);
+                                errors.push(ParserError::MissingClosingBracket(open));
+                                close(&mut stack, &mut output, &mut arguments, false);
+                                previous = Some(&closed);
+                            }
+                            Some(_) => output.push(stack.pop().unwrap()),
+                            None => break,
                         }
-                        Some(_) => output.push(stack.pop().unwrap()),
-                        None => return Err(ParserError::MissingOpeningBracket(position)),
                     }
-                },
+                    if stack.is_empty() {
+                        errors.push(ParserError::MissingOpeningBracket(Span::of(position, token)));
+                        continue;
+                    }
+                    let empty = previous == Some(&Token::OpenBracket);
+                    close(&mut stack, &mut output, &mut arguments, empty);
+                }
+                // A term only follows `history`, which the keyword takes along
+                Token::Call(..) | Token::Assignment(_) | Token::Term(_) => unreachable!(),
             }
+            previous = Some(token);
         }
         loop {
             match stack.last() {
-                Some(&(position, Token::OpenBracket)) => {
-                    return Err(ParserError::MissingClosingBracket(position))
+                Some(&(position, ref token @ Token::OpenBracket))
+                | Some(&(position, ref token @ Token::OpenSquareBracket)) => {
+                    errors.push(ParserError::MissingClosingBracket(Span::of(position, token)));
+                    let empty = previous == Some(token);
+                    close(&mut stack, &mut output, &mut arguments, empty);
+                    previous = Some(&closed);
                 }
                 Some(_) => output.push(stack.pop().unwrap()),
                 None => break,
@@ -105,34 +313,142 @@ impl Parser {
     }
 }
 
+// Pop the bracket on top of the stack, once the operators above it are
+// output. A call bracket outputs its call, `empty` when nothing was inside,
+// and a square bracket outputs its bit index or slice.
+fn close(stack: &mut Tokens, output: &mut Tokens, arguments: &mut Vec<Option<usize>>, empty: bool) {
+    let (open_position, open) = stack.pop().unwrap();
+    let count = arguments.pop().unwrap();
+    match (open, count) {
+        (Token::OpenSquareBracket, Some(1)) => {
+            output.push((open_position, Token::Operator(Symbol::INDEX)))
+        }
+        (Token::OpenSquareBracket, _) => output.push((open_position, Token::Operator(Symbol::SLICE))),
+        (_, Some(count)) => match stack.pop() {
+            Some((call_position, Token::Function(name))) => {
+                output.push((call_position, Token::Call(name, if empty { 0 } else { count })))
+            }
+            _ => unreachable!(),
+        },
+        (_, None) => {}
+    }
+}
+
+// The result of a parse, unless errors were recovered from along the way
+fn finish<T>(
+    result: Result<T, ParserError>,
+    mut errors: Vec<ParserError>,
+) -> Result<T, Vec<ParserError>> {
+    match result {
+        Ok(value) if errors.is_empty() => Ok(value),
+        Ok(_) => Err(errors),
+        Err(error) => {
+            errors.push(error);
+            Err(errors)
+        }
+    }
+}
+
 impl Default for Parser {
     fn default() -> Parser {
         let mut parser = Parser::new();
 
+        parser.operators.insert(
+            Symbol::POW,
+            Operator::new(1, Associativity::RightToLeft)
+        );
         parser.operators.insert(
             Symbol::NOT,
             Operator::new(2, Associativity::RightToLeft)
         );
         parser.operators.insert(
-            Symbol::RSHIFT,
+            Symbol::NEG,
+            Operator::new(2, Associativity::RightToLeft)
+        );
+        parser.operators.insert(
+            Symbol::LNOT,
+            Operator::new(2, Associativity::RightToLeft)
+        );
+        parser.operators.insert(
+            Symbol::MUL,
             Operator::new(3, Associativity::LeftToRight)
         );
         parser.operators.insert(
-            Symbol::LSHIFT,
+            Symbol::DIV,
             Operator::new(3, Associativity::LeftToRight)
         );
         parser.operators.insert(
-            Symbol::AND,
+            Symbol::MOD,
+            Operator::new(3, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::ADD,
             Operator::new(4, Associativity::LeftToRight)
         );
         parser.operators.insert(
-            Symbol::XOR,
+            Symbol::SUB,
+            Operator::new(4, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::RSHIFT,
             Operator::new(5, Associativity::LeftToRight)
         );
         parser.operators.insert(
-            Symbol::OR,
+            Symbol::LSHIFT,
+            Operator::new(5, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::ROTR,
+            Operator::new(5, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::ROTL,
+            Operator::new(5, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::LT,
+            Operator::new(6, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::LE,
+            Operator::new(6, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::GT,
             Operator::new(6, Associativity::LeftToRight)
         );
+        parser.operators.insert(
+            Symbol::GE,
+            Operator::new(6, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::EQ,
+            Operator::new(7, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::NE,
+            Operator::new(7, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::AND,
+            Operator::new(8, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::XOR,
+            Operator::new(9, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::OR,
+            Operator::new(10, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::LAND,
+            Operator::new(11, Associativity::LeftToRight)
+        );
+        parser.operators.insert(
+            Symbol::LOR,
+            Operator::new(12, Associativity::LeftToRight)
+        );
 
         parser
     }
@@ -140,16 +456,18 @@ impl Default for Parser {
 
 #[cfg(test)]
 mod tests {
+    use ast::{self, Span, Statement};
     use lexer::{Symbol, Token, Tokens};
     use parser::Parser;
     use error::ParserError;
+    use function::Definition;
 
     #[test]
     fn blank() {
         let parser = Parser::default();
         let tokens: Tokens = vec![];
         let parsed: Tokens = vec![];
-        assert_eq!(parser.parse(tokens).unwrap(), parsed);
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
     }
 
     #[test]
@@ -171,7 +489,7 @@ mod tests {
             (8, Token::Operator(Symbol::LSHIFT)),
             (3, Token::Operator(Symbol::OR)),
         ];
-        assert_eq!(parser.parse(tokens).unwrap(), parsed);
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
     }
 
     #[test]
@@ -193,7 +511,175 @@ mod tests {
             (8, Token::Operator(Symbol::OR)),
         ];
 
-        assert_eq!(parser.parse(tokens).unwrap(), parsed);
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn expression_left_to_right() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::LSHIFT)),
+            (5, Token::Decimal("2".to_string())),
+            (7, Token::Operator(Symbol::LSHIFT)),
+            (10, Token::Decimal("3".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (5, Token::Decimal("2".to_string())),
+            (2, Token::Operator(Symbol::LSHIFT)),
+            (10, Token::Decimal("3".to_string())),
+            (7, Token::Operator(Symbol::LSHIFT)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn expression_right_to_left() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::NOT)),
+            (1, Token::Operator(Symbol::NEG)),
+            (2, Token::Operator(Symbol::NOT)),
+            (3, Token::Decimal("1".to_string())),
+            (5, Token::Operator(Symbol::AND)),
+            (7, Token::Operator(Symbol::NEG)),
+            (8, Token::Decimal("2".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (3, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::NOT)),
+            (1, Token::Operator(Symbol::NEG)),
+            (0, Token::Operator(Symbol::NOT)),
+            (8, Token::Decimal("2".to_string())),
+            (7, Token::Operator(Symbol::NEG)),
+            (5, Token::Operator(Symbol::AND)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn expression_arithmetic_precedence() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (2, Token::Operator(Symbol::ADD)),
+            (4, Token::Decimal("2".to_string())),
+            (6, Token::Operator(Symbol::MUL)),
+            (8, Token::Decimal("3".to_string())),
+            (10, Token::Operator(Symbol::LSHIFT)),
+            (13, Token::Decimal("4".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (4, Token::Decimal("2".to_string())),
+            (8, Token::Decimal("3".to_string())),
+            (6, Token::Operator(Symbol::MUL)),
+            (2, Token::Operator(Symbol::ADD)),
+            (13, Token::Decimal("4".to_string())),
+            (10, Token::Operator(Symbol::LSHIFT)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn expression_pow_binds_tighter_than_neg() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::NEG)),
+            (1, Token::Decimal("2".to_string())),
+            (3, Token::Operator(Symbol::POW)),
+            (6, Token::Decimal("3".to_string())),
+            (8, Token::Operator(Symbol::POW)),
+            (11, Token::Decimal("2".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (1, Token::Decimal("2".to_string())),
+            (6, Token::Decimal("3".to_string())),
+            (11, Token::Decimal("2".to_string())),
+            (8, Token::Operator(Symbol::POW)),
+            (3, Token::Operator(Symbol::POW)),
+            (0, Token::Operator(Symbol::NEG)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn expression_prefix_after_pow() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("2".to_string())),
+            (2, Token::Operator(Symbol::POW)),
+            (5, Token::Operator(Symbol::NEG)),
+            (6, Token::Decimal("1".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (0, Token::Decimal("2".to_string())),
+            (6, Token::Decimal("1".to_string())),
+            (5, Token::Operator(Symbol::NEG)),
+            (2, Token::Operator(Symbol::POW)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("2".to_string())),
+            (2, Token::Operator(Symbol::POW)),
+            (5, Token::Operator(Symbol::NOT)),
+            (6, Token::Decimal("0".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (0, Token::Decimal("2".to_string())),
+            (6, Token::Decimal("0".to_string())),
+            (5, Token::Operator(Symbol::NOT)),
+            (2, Token::Operator(Symbol::POW)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn expression_neg_before_pow() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::NEG)),
+            (1, Token::Decimal("2".to_string())),
+            (3, Token::Operator(Symbol::POW)),
+            (6, Token::Decimal("2".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (1, Token::Decimal("2".to_string())),
+            (6, Token::Decimal("2".to_string())),
+            (3, Token::Operator(Symbol::POW)),
+            (0, Token::Operator(Symbol::NEG)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn expression_comparison_precedence() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("3".to_string())),
+            (2, Token::Operator(Symbol::AND)),
+            (4, Token::Decimal("1".to_string())),
+            (6, Token::Operator(Symbol::EQ)),
+            (9, Token::Decimal("1".to_string())),
+            (11, Token::Operator(Symbol::LOR)),
+            (14, Token::Decimal("2".to_string())),
+            (16, Token::Operator(Symbol::LT)),
+            (18, Token::Decimal("1".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (0, Token::Decimal("3".to_string())),
+            (4, Token::Decimal("1".to_string())),
+            (9, Token::Decimal("1".to_string())),
+            (6, Token::Operator(Symbol::EQ)),
+            (2, Token::Operator(Symbol::AND)),
+            (14, Token::Decimal("2".to_string())),
+            (18, Token::Decimal("1".to_string())),
+            (16, Token::Operator(Symbol::LT)),
+            (11, Token::Operator(Symbol::LOR)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
     }
 
     #[test]
@@ -215,7 +701,7 @@ mod tests {
             (8, Token::Operator(Symbol::LSHIFT)),
             (3, Token::Operator(Symbol::OR)),
         ];
-        assert_eq!(parser.parse(tokens).unwrap(), parsed);
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
     }
 
     #[test]
@@ -233,7 +719,70 @@ mod tests {
             (11, Token::Hexadecimal("0xf".to_string())),
             (8, Token::Operator(Symbol::LSHIFT)),
         ];
-        assert_eq!(parser.parse(tokens).unwrap(), parsed);
+        assert_eq!(parser.postfix(tokens.clone()).unwrap(), parsed);
+        assert_eq!(
+            parser.parse(tokens),
+            Err(vec![ParserError::TooManyArguments(Span::new(6, 14))])
+        );
+    }
+
+    #[test]
+    fn function_call() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Function("max".to_string())),
+            (3, Token::OpenBracket),
+            (4, Token::Decimal("1".to_string())),
+            (5, Token::Comma),
+            (7, Token::Function("popcount".to_string())),
+            (15, Token::OpenBracket),
+            (16, Token::Decimal("2".to_string())),
+            (18, Token::Operator(Symbol::OR)),
+            (20, Token::Decimal("3".to_string())),
+            (21, Token::CloseBracket),
+            (22, Token::CloseBracket),
+            (24, Token::Operator(Symbol::ADD)),
+            (26, Token::Decimal("4".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (4, Token::Decimal("1".to_string())),
+            (16, Token::Decimal("2".to_string())),
+            (20, Token::Decimal("3".to_string())),
+            (18, Token::Operator(Symbol::OR)),
+            (7, Token::Call("popcount".to_string(), 1)),
+            (0, Token::Call("max".to_string(), 2)),
+            (26, Token::Decimal("4".to_string())),
+            (24, Token::Operator(Symbol::ADD)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn function_call_empty() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Function("f".to_string())),
+            (1, Token::OpenBracket),
+            (2, Token::CloseBracket),
+        ];
+        let parsed: Tokens = vec![(0, Token::Call("f".to_string(), 0))];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn comma_outside_call() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Function("f".to_string())),
+            (1, Token::OpenBracket),
+            (2, Token::OpenBracket),
+            (3, Token::Decimal("1".to_string())),
+            (4, Token::Comma),
+            (6, Token::Decimal("2".to_string())),
+            (7, Token::CloseBracket),
+            (8, Token::CloseBracket),
+        ];
+        assert_eq!(parser.postfix(tokens), Err(vec![ParserError::MisplacedComma(Span::new(4, 5))]));
     }
 
     #[test]
@@ -241,7 +790,7 @@ mod tests {
         let parser = Parser::default();
         let tokens: Tokens = vec![(0, Token::Keyword("exit".to_string()))];
         let parsed: Tokens = vec![(0, Token::Keyword("exit".to_string()))];
-        assert_eq!(parser.parse(tokens).unwrap(), parsed);
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
     }
 
     #[test]
@@ -256,8 +805,8 @@ mod tests {
             (13, Token::CloseBracket),
         ];
         assert_eq!(
-            parser.parse(tokens),
-            Err(ParserError::MissingOpeningBracket(13))
+            parser.postfix(tokens),
+            Err(vec![ParserError::MissingOpeningBracket(Span::new(13, 14))])
         );
     }
 
@@ -272,10 +821,84 @@ mod tests {
             (8, Token::Operator(Symbol::LSHIFT)),
             (11, Token::Decimal("12".to_string())),
         ];
+        assert_eq!(
+            parser.postfix(tokens),
+            Err(vec![ParserError::MissingClosingBracket(Span::new(5, 6))])
+        );
+    }
+
+    #[test]
+    fn bracket_recovery() {
+        let parser = Parser::default();
+        // 1) + (2
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (1, Token::CloseBracket),
+            (3, Token::Operator(Symbol::ADD)),
+            (5, Token::OpenBracket),
+            (6, Token::Decimal("2".to_string())),
+        ];
         assert_eq!(
             parser.parse(tokens),
-            Err(ParserError::MissingClosingBracket(5))
+            Err(vec![
+                ParserError::MissingOpeningBracket(Span::new(1, 2)),
+                ParserError::MissingClosingBracket(Span::new(5, 6)),
+            ])
         );
+
+        // popcount(1]
+        let tokens: Tokens = vec![
+            (0, Token::Function("popcount".to_string())),
+            (8, Token::OpenBracket),
+            (9, Token::Decimal("1".to_string())),
+            (10, Token::CloseSquareBracket),
+        ];
+        assert_eq!(
+            parser.parse(tokens),
+            Err(vec![
+                ParserError::MissingClosingBracket(Span::new(8, 9)),
+                ParserError::MissingOpeningBracket(Span::new(10, 11)),
+            ])
+        );
+
+        // Errors other than brackets still end the parse, after any found before
+        // (1 +
+        let tokens: Tokens = vec![
+            (0, Token::OpenBracket),
+            (1, Token::Decimal("1".to_string())),
+            (3, Token::Operator(Symbol::ADD)),
+        ];
+        assert_eq!(
+            parser.parse(tokens),
+            Err(vec![
+                ParserError::MissingClosingBracket(Span::new(0, 1)),
+                ParserError::MissingArgument(Span::new(3, 4)),
+            ])
+        );
+    }
+
+    #[test]
+    fn keyword_argument() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Keyword("width".to_string())),
+            (6, Token::Decimal("64".to_string())),
+        ];
+        let parsed: Tokens = vec![
+            (0, Token::Keyword("width".to_string())),
+            (6, Token::Decimal("64".to_string())),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), parsed);
+    }
+
+    #[test]
+    fn keyword_after_literal() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("64".to_string())),
+            (3, Token::Keyword("width".to_string())),
+        ];
+        assert_eq!(parser.postfix(tokens), Err(vec![ParserError::KeywordError(Span::new(3, 8))]));
     }
 
     #[test]
@@ -285,7 +908,7 @@ mod tests {
             (0, Token::Keyword("exit".to_string())),
             (5, Token::Keyword("help".to_string())),
         ];
-        assert_eq!(parser.parse(tokens), Err(ParserError::KeywordError(0)));
+        assert_eq!(parser.postfix(tokens), Err(vec![ParserError::KeywordError(Span::new(0, 4))]));
     }
 
     #[test]
@@ -296,7 +919,211 @@ mod tests {
             (5, Token::Operator(Symbol::OR)),
             (7, Token::Keyword("help".to_string())),
         ];
-        assert_eq!(parser.parse(tokens), Err(ParserError::KeywordError(0)));
+        assert_eq!(parser.postfix(tokens), Err(vec![ParserError::KeywordError(Span::new(0, 4))]));
+    }
+
+    #[test]
+    fn slice_binds_tighter_than_not() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Operator(Symbol::NOT)),
+            (1, Token::Decimal("5".to_string())),
+            (2, Token::OpenSquareBracket),
+            (3, Token::Decimal("2".to_string())),
+            (4, Token::Operator(Symbol::ADD)),
+            (5, Token::Decimal("1".to_string())),
+            (6, Token::Colon),
+            (7, Token::Decimal("0".to_string())),
+            (8, Token::CloseSquareBracket),
+        ];
+        let result: Tokens = vec![
+            (1, Token::Decimal("5".to_string())),
+            (3, Token::Decimal("2".to_string())),
+            (5, Token::Decimal("1".to_string())),
+            (4, Token::Operator(Symbol::ADD)),
+            (7, Token::Decimal("0".to_string())),
+            (2, Token::Operator(Symbol::SLICE)),
+            (0, Token::Operator(Symbol::NOT)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), result);
+    }
+
+    #[test]
+    fn index_after_bracket() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::OpenBracket),
+            (1, Token::Decimal("6".to_string())),
+            (2, Token::CloseBracket),
+            (3, Token::OpenSquareBracket),
+            (4, Token::Decimal("1".to_string())),
+            (5, Token::CloseSquareBracket),
+        ];
+        let result: Tokens = vec![
+            (1, Token::Decimal("6".to_string())),
+            (4, Token::Decimal("1".to_string())),
+            (3, Token::Operator(Symbol::INDEX)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), result);
+    }
+
+    #[test]
+    fn slice_errors() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::OpenSquareBracket),
+            (1, Token::Decimal("1".to_string())),
+            (2, Token::CloseSquareBracket),
+        ];
+        assert_eq!(parser.postfix(tokens), Err(vec![ParserError::MisplacedSlice(Span::new(0, 1))]));
+
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (1, Token::OpenSquareBracket),
+            (2, Token::Decimal("1".to_string())),
+            (3, Token::Colon),
+            (4, Token::CloseSquareBracket),
+        ];
+        assert_eq!(parser.postfix(tokens), Err(vec![ParserError::EmptySlice(Span::new(4, 5))]));
+
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (1, Token::Colon),
+            (2, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(parser.postfix(tokens), Err(vec![ParserError::MisplacedColon(Span::new(1, 2))]));
+
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (1, Token::OpenSquareBracket),
+            (2, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(
+            parser.postfix(tokens),
+            Err(vec![ParserError::MissingClosingBracket(Span::new(1, 2))])
+        );
     }
 
+    #[test]
+    fn assignment() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("mask".to_string())),
+            (5, Token::Assign),
+            (7, Token::Hexadecimal("0xff".to_string())),
+            (12, Token::Operator(Symbol::LSHIFT)),
+            (15, Token::Identifier("n".to_string())),
+        ];
+        let result: Tokens = vec![
+            (7, Token::Hexadecimal("0xff".to_string())),
+            (15, Token::Identifier("n".to_string())),
+            (12, Token::Operator(Symbol::LSHIFT)),
+            (0, Token::Assignment("mask".to_string())),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), result);
+    }
+
+    #[test]
+    fn assignment_misplaced() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Decimal("1".to_string())),
+            (2, Token::Assign),
+            (4, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(
+            parser.postfix(tokens),
+            Err(vec![ParserError::MisplacedAssignment(Span::new(2, 3))])
+        );
+
+        let tokens: Tokens = vec![
+            (0, Token::Identifier("a".to_string())),
+            (2, Token::Assign),
+        ];
+        assert_eq!(
+            parser.postfix(tokens),
+            Err(vec![ParserError::MisplacedAssignment(Span::new(2, 3))])
+        );
+    }
+
+    #[test]
+    fn slots() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::LastResult),
+            (2, Token::Operator(Symbol::ADD)),
+            (4, Token::Slot(1)),
+        ];
+        let result: Tokens = vec![
+            (0, Token::LastResult),
+            (4, Token::Slot(1)),
+            (2, Token::Operator(Symbol::ADD)),
+        ];
+        assert_eq!(parser.postfix(tokens).unwrap(), result);
+    }
+
+    #[test]
+    fn definition() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Def),
+            (4, Token::Function("inc".to_string())),
+            (7, Token::OpenBracket),
+            (8, Token::Identifier("x".to_string())),
+            (9, Token::CloseBracket),
+            (11, Token::Assign),
+            (13, Token::Identifier("x".to_string())),
+            (14, Token::Operator(Symbol::ADD)),
+            (15, Token::Decimal("1".to_string())),
+        ];
+        let body: Tokens = vec![
+            (13, Token::Identifier("x".to_string())),
+            (15, Token::Decimal("1".to_string())),
+            (14, Token::Operator(Symbol::ADD)),
+        ];
+        let definition = Definition {
+            params: vec!["x".to_string()],
+            body: ast::expression(&body).unwrap(),
+            text: "x+1".to_string(),
+        };
+        let result = Statement::Definition("inc".to_string(), Span::new(4, 7), definition);
+        assert_eq!(parser.parse(tokens).unwrap(), result);
+    }
+
+    #[test]
+    fn definition_invalid() {
+        let parser = Parser::default();
+        let tokens: Tokens = vec![
+            (0, Token::Def),
+            (4, Token::Function("f".to_string())),
+            (5, Token::OpenBracket),
+            (6, Token::Identifier("x".to_string())),
+            (7, Token::Comma),
+            (8, Token::Identifier("x".to_string())),
+            (9, Token::CloseBracket),
+            (11, Token::Assign),
+            (13, Token::Identifier("x".to_string())),
+        ];
+        assert_eq!(parser.parse(tokens), Err(vec![ParserError::DefinitionError(Span::new(8, 9))]));
+
+        let tokens: Tokens = vec![
+            (0, Token::Def),
+            (4, Token::Function("f".to_string())),
+            (5, Token::OpenBracket),
+            (6, Token::Decimal("1".to_string())),
+            (7, Token::CloseBracket),
+            (9, Token::Assign),
+            (11, Token::Decimal("1".to_string())),
+        ];
+        assert_eq!(parser.parse(tokens), Err(vec![ParserError::DefinitionError(Span::new(6, 7))]));
+
+        let tokens: Tokens = vec![
+            (0, Token::Def),
+            (4, Token::Function("f".to_string())),
+            (5, Token::OpenBracket),
+            (6, Token::CloseBracket),
+            (8, Token::Assign),
+        ];
+        assert_eq!(parser.parse(tokens), Err(vec![ParserError::DefinitionError(Span::new(8, 9))]));
+    }
 }
@@ -0,0 +1,543 @@
+use std::convert::TryFrom;
+use ast::Span;
+use error::EvaluatorError;
+use lexer::Symbol;
+use mode::Mode;
+
+// An operator or builtin function, taking its arguments off the stack
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Not,
+    Neg,
+    And,
+    Or,
+    Xor,
+    RShift,
+    LShift,
+    RotL,
+    RotR,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LAnd,
+    LOr,
+    LNot,
+    Index,
+    Slice,
+    Popcount,
+    Clz,
+    Ctz,
+    Parity,
+    Bswap,
+    Bitrev,
+    Log2,
+    IsPow2,
+    NextPow2,
+    Bit,
+    Genmask,
+    FieldGet,
+    FieldPrep,
+    SetBit,
+    ClearBit,
+    ToggleBit,
+    TestBit,
+    Extract,
+    Insert,
+}
+
+impl Op {
+    pub fn from_symbol(symbol: &Symbol) -> Option<Op> {
+        let op = match *symbol {
+            Symbol::NOT => Op::Not,
+            Symbol::NEG => Op::Neg,
+            Symbol::AND => Op::And,
+            Symbol::OR => Op::Or,
+            Symbol::XOR => Op::Xor,
+            Symbol::RSHIFT => Op::RShift,
+            Symbol::LSHIFT => Op::LShift,
+            Symbol::ROTL => Op::RotL,
+            Symbol::ROTR => Op::RotR,
+            Symbol::ADD => Op::Add,
+            Symbol::SUB => Op::Sub,
+            Symbol::MUL => Op::Mul,
+            Symbol::DIV => Op::Div,
+            Symbol::MOD => Op::Rem,
+            Symbol::POW => Op::Pow,
+            Symbol::EQ => Op::Eq,
+            Symbol::NE => Op::Ne,
+            Symbol::LT => Op::Lt,
+            Symbol::LE => Op::Le,
+            Symbol::GT => Op::Gt,
+            Symbol::GE => Op::Ge,
+            Symbol::LAND => Op::LAnd,
+            Symbol::LOR => Op::LOr,
+            Symbol::LNOT => Op::LNot,
+            Symbol::INDEX => Op::Index,
+            Symbol::SLICE => Op::Slice,
+            Symbol::Function(ref name) => match name.as_str() {
+                "popcount" => Op::Popcount,
+                "clz" => Op::Clz,
+                "ctz" => Op::Ctz,
+                "parity" => Op::Parity,
+                "bswap" => Op::Bswap,
+                "bitrev" => Op::Bitrev,
+                "log2" => Op::Log2,
+                "ispow2" => Op::IsPow2,
+                "nextpow2" => Op::NextPow2,
+                "BIT" => Op::Bit,
+                "GENMASK" => Op::Genmask,
+                "FIELD_GET" => Op::FieldGet,
+                "FIELD_PREP" => Op::FieldPrep,
+                "setbit" => Op::SetBit,
+                "clearbit" => Op::ClearBit,
+                "togglebit" => Op::ToggleBit,
+                "testbit" => Op::TestBit,
+                "extract" => Op::Extract,
+                "insert" => Op::Insert,
+                _ => return None,
+            },
+        };
+        Some(op)
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Op::Not | Op::Neg | Op::LNot => 1,
+            Op::Popcount | Op::Clz | Op::Ctz | Op::Parity | Op::Bswap | Op::Bitrev => 1,
+            Op::Log2 | Op::IsPow2 | Op::NextPow2 | Op::Bit => 1,
+            Op::Slice | Op::Extract => 3,
+            Op::Insert => 4,
+            _ => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Push(u128),
+    Input,
+    Apply(Op, Span), // With the span to report errors at
+    Skip(Op, usize), // Jump to an index when the value on top decides && or ||
+}
+
+// RPN compiled for a fixed width, which runs without tracing or allocating.
+// Values are bit patterns in a u128 and give the same results and errors
+// as the evaluator's functions.
+pub struct Program {
+    code: Vec<Instruction>,
+    stack: Vec<u128>,
+    bits: u32,
+    signed: bool,
+}
+
+fn boolean(value: bool) -> u128 {
+    u128::from(value)
+}
+
+impl Program {
+    // None without a fixed width, or when the code would not leave exactly one
+    // value, so the evaluator can report the error instead
+    pub fn new(code: Vec<Instruction>, mode: Mode) -> Option<Program> {
+        let bits = mode.width.bits()?;
+        let mut depth = 0;
+        let mut max_depth = 0;
+        for instruction in &code {
+            match *instruction {
+                Instruction::Push(_) | Instruction::Input => depth += 1,
+                Instruction::Apply(op, _) if depth >= op.arity() => depth -= op.arity() - 1,
+                Instruction::Apply(..) => return None,
+                Instruction::Skip(..) if depth >= 1 => {}
+                Instruction::Skip(..) => return None,
+            }
+            max_depth = max_depth.max(depth);
+        }
+        if depth != 1 {
+            return None;
+        }
+
+        Some(Program {
+            code,
+            stack: Vec::with_capacity(max_depth),
+            bits,
+            signed: mode.signed,
+        })
+    }
+
+    pub fn run(&mut self, input: u128) -> Result<u128, EvaluatorError> {
+        let input = input & self.mask();
+        self.stack.clear();
+        let mut index = 0;
+        while index < self.code.len() {
+            match self.code[index] {
+                Instruction::Push(value) => self.stack.push(value),
+                Instruction::Input => self.stack.push(input),
+                Instruction::Apply(op, span) => {
+                    let mut args = [0; 4];
+                    for arg in args[..op.arity()].iter_mut().rev() {
+                        *arg = self.stack.pop().unwrap();
+                    }
+                    let value = self.apply(op, args, span)?;
+                    self.stack.push(value);
+                }
+                Instruction::Skip(op, target) => {
+                    let top = self.stack.last_mut().unwrap();
+                    // 0 decides && and anything else decides ||
+                    if (op == Op::LAnd) == (*top == 0) {
+                        *top = boolean(op == Op::LOr);
+                        index = target;
+                        continue;
+                    }
+                }
+            }
+            index += 1;
+        }
+        Ok(self.stack[0])
+    }
+
+    fn mask(&self) -> u128 {
+        u128::MAX >> (128 - self.bits)
+    }
+
+    // Two's complement value of a bit pattern
+    fn signed_value(&self, a: u128) -> i128 {
+        let shift = 128 - self.bits;
+        ((a << shift) as i128) >> shift
+    }
+
+    // Exact results must fit the width, as the evaluator's `arithmetic` checks
+    fn unsigned_result(&self, value: Option<u128>, span: Span) -> Result<u128, EvaluatorError> {
+        match value {
+            Some(value) if value <= self.mask() => Ok(value),
+            _ => Err(EvaluatorError::Overflow(span)),
+        }
+    }
+
+    fn signed_result(&self, value: Option<i128>, span: Span) -> Result<u128, EvaluatorError> {
+        match value {
+            Some(value) if self.signed_value(value as u128 & self.mask()) == value => {
+                Ok(value as u128 & self.mask())
+            }
+            _ => Err(EvaluatorError::Overflow(span)),
+        }
+    }
+
+    fn shift_amount(&self, b: u128, span: Span) -> Result<u32, EvaluatorError> {
+        if self.signed && self.signed_value(b) < 0 {
+            return Err(EvaluatorError::NegativeShift(span));
+        }
+        if b >= u128::from(self.bits) {
+            return Err(EvaluatorError::OverflowShift(span));
+        }
+        Ok(b as u32)
+    }
+
+    // Widths are powers of two, so the amount modulo the width is in its low
+    // bits whether the value is signed or not
+    fn rotation(&self, b: u128) -> u32 {
+        (b & u128::from(self.bits - 1)) as u32
+    }
+
+    fn bit_index(&self, n: u128, span: Span) -> Result<u32, EvaluatorError> {
+        if (self.signed && self.signed_value(n) < 0) || n >= u128::from(self.bits) {
+            return Err(EvaluatorError::BitOutOfRange(span));
+        }
+        Ok(n as u32)
+    }
+
+    fn bit_range(&self, hi: u128, lo: u128, span: Span) -> Result<(u32, u32), EvaluatorError> {
+        let hi = self.bit_index(hi, span)?;
+        let lo = self.bit_index(lo, span)?;
+        if hi < lo {
+            return Err(EvaluatorError::ReversedBounds(span));
+        }
+        Ok((hi, lo))
+    }
+
+    // Mask with bits hi down to lo set, inclusive
+    fn field_mask(hi: u32, lo: u32) -> u128 {
+        (u128::MAX >> (127 - (hi - lo))) << lo
+    }
+
+    fn apply(&self, op: Op, args: [u128; 4], span: Span) -> Result<u128, EvaluatorError> {
+        let [a, b, c, d] = args;
+        let (x, y) = (self.signed_value(a), self.signed_value(b));
+        let value = match op {
+            Op::Not => !a & self.mask(),
+            Op::Neg => a.wrapping_neg() & self.mask(),
+            Op::And => a & b,
+            Op::Or => a | b,
+            Op::Xor => a ^ b,
+            Op::RShift => {
+                let amount = self.shift_amount(b, span)?;
+                if self.signed {
+                    (x >> amount) as u128 & self.mask()
+                } else {
+                    a >> amount
+                }
+            }
+            Op::LShift => (a << self.shift_amount(b, span)?) & self.mask(),
+            Op::RotL | Op::RotR => {
+                let amount = self.rotation(b);
+                if amount == 0 {
+                    a
+                } else if op == Op::RotL {
+                    ((a << amount) | (a >> (self.bits - amount))) & self.mask()
+                } else {
+                    ((a >> amount) | (a << (self.bits - amount))) & self.mask()
+                }
+            }
+            Op::Add if self.signed => self.signed_result(x.checked_add(y), span)?,
+            Op::Add => self.unsigned_result(a.checked_add(b), span)?,
+            Op::Sub if self.signed => self.signed_result(x.checked_sub(y), span)?,
+            Op::Sub => self.unsigned_result(a.checked_sub(b), span)?,
+            Op::Mul if self.signed => self.signed_result(x.checked_mul(y), span)?,
+            Op::Mul => self.unsigned_result(a.checked_mul(b), span)?,
+            Op::Div | Op::Rem if b == 0 => return Err(EvaluatorError::DivisionByZero(span)),
+            Op::Div if self.signed => self.signed_result(x.checked_div(y), span)?,
+            Op::Div => a / b,
+            Op::Rem if self.signed => self.signed_result(Some(x.wrapping_rem(y)), span)?,
+            Op::Rem => a % b,
+            Op::Pow if self.signed => {
+                if y < 0 {
+                    return Err(EvaluatorError::NegativeExponent(span));
+                }
+                let power = match x {
+                    -1 if y % 2 == 1 => Some(-1),
+                    0 if y != 0 => Some(0),
+                    -1..=1 => Some(1),
+                    _ if y > i128::from(u32::MAX) => None,
+                    _ => x.checked_pow(y as u32),
+                };
+                self.signed_result(power, span)?
+            }
+            Op::Pow => {
+                let power = match a {
+                    0 if b != 0 => Some(0),
+                    0 | 1 => Some(1),
+                    _ if b > u128::from(u32::MAX) => None,
+                    _ => a.checked_pow(b as u32),
+                };
+                self.unsigned_result(power, span)?
+            }
+            Op::Eq => boolean(a == b),
+            Op::Ne => boolean(a != b),
+            Op::Lt if self.signed => boolean(x < y),
+            Op::Lt => boolean(a < b),
+            Op::Le if self.signed => boolean(x <= y),
+            Op::Le => boolean(a <= b),
+            Op::Gt if self.signed => boolean(x > y),
+            Op::Gt => boolean(a > b),
+            Op::Ge if self.signed => boolean(x >= y),
+            Op::Ge => boolean(a >= b),
+            Op::LAnd => boolean(a != 0 && b != 0),
+            Op::LOr => boolean(a != 0 || b != 0),
+            Op::LNot => boolean(a == 0),
+            Op::Index => (a >> self.bit_index(b, span)?) & 1,
+            Op::Slice | Op::Extract => {
+                let (hi, lo) = self.bit_range(b, c, span)?;
+                (a & Program::field_mask(hi, lo)) >> lo
+            }
+            Op::Popcount => u128::from(a.count_ones()),
+            Op::Clz => u128::from(a.leading_zeros() - (128 - self.bits)),
+            Op::Ctz if a == 0 => u128::from(self.bits),
+            Op::Ctz => u128::from(a.trailing_zeros()),
+            Op::Parity => u128::from(a.count_ones() & 1),
+            Op::Bswap => a.swap_bytes() >> (128 - self.bits),
+            Op::Bitrev => a.reverse_bits() >> (128 - self.bits),
+            Op::Log2 => {
+                let positive = if self.signed { x > 0 } else { a > 0 };
+                if !positive {
+                    return Err(EvaluatorError::Domain(span));
+                }
+                u128::from(127 - a.leading_zeros())
+            }
+            Op::IsPow2 => boolean((!self.signed || x > 0) && a.is_power_of_two()),
+            Op::NextPow2 => {
+                if self.signed && x < 0 {
+                    return Err(EvaluatorError::Domain(span));
+                }
+                let power = if a <= 1 {
+                    Some(1)
+                } else {
+                    1u128.checked_shl(128 - (a - 1).leading_zeros())
+                };
+                if self.signed {
+                    let power = power.and_then(|power| i128::try_from(power).ok());
+                    self.signed_result(power, span)?
+                } else {
+                    self.unsigned_result(power, span)?
+                }
+            }
+            Op::Bit => 1 << self.bit_index(a, span)?,
+            Op::Genmask => {
+                let (hi, lo) = self.bit_range(a, b, span)?;
+                Program::field_mask(hi, lo)
+            }
+            Op::FieldGet | Op::FieldPrep if a == 0 => return Err(EvaluatorError::Domain(span)),
+            Op::FieldGet => (b & a) >> a.trailing_zeros(),
+            Op::FieldPrep => (b << a.trailing_zeros()) & a,
+            Op::SetBit => a | 1 << self.bit_index(b, span)?,
+            Op::ClearBit => a & !(1 << self.bit_index(b, span)?),
+            Op::ToggleBit => a ^ 1 << self.bit_index(b, span)?,
+            Op::TestBit => (a >> self.bit_index(b, span)?) & 1,
+            Op::Insert => {
+                let (hi, lo) = self.bit_range(c, d, span)?;
+                let mask = Program::field_mask(hi, lo);
+                (a & !mask) | ((b << lo) & mask)
+            }
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hint::black_box;
+    use std::time::Instant;
+    use num_bigint::BigInt;
+    use num_traits::ToPrimitive;
+    use evaluator::Evaluator;
+    use ast::{Expr, Span, Statement};
+    use error::EvaluatorError;
+    use lexer::lexer;
+    use mode::Width;
+    use parser::Parser;
+
+    fn statement(line: &str, width: Width) -> Statement {
+        Parser::default().parse(lexer(line, width).unwrap()).unwrap()
+    }
+
+    fn expression(line: &str, width: Width) -> Expr {
+        match statement(line, width) {
+            Statement::Expression(expr) => expr,
+            statement => panic!("{:?} is not an expression", statement),
+        }
+    }
+
+    fn evaluator(width: Width, signed: bool) -> Evaluator {
+        let mut evaluator = Evaluator::default();
+        evaluator.set_width(width);
+        evaluator.set_signed(signed);
+        evaluator
+    }
+
+    const EXPRESSIONS: &[&str] = &[
+        "~x", "-x", "x & 0x5a", "x | 3", "x ^ 0xff", "-1 >> 1",
+        "x >> 3", "x << 3", "x >> x", "x << x", "x <<< 3", "x >>> x",
+        "x + 100", "x - 1", "1 - x", "x * x", "x * 3", "100 / x", "x / 3",
+        "x % 7", "7 % x", "x ** 2", "2 ** x", "x ** x", "(0 - 1) ** x",
+        "x == 3", "x != 3", "x < 100", "x <= 0x80", "x > 1", "x >= 2",
+        "x && 1", "x || 0", "x && 100 / x", "x || 1 / (x - x)", "!x", "x[3]", "x[x]", "x[7:4]", "x[x:1]",
+        "popcount(x)", "clz(x)", "ctz(x)", "parity(x)", "bswap(x)",
+        "bitrev(x)", "log2(x)", "ispow2(x)", "nextpow2(x)", "BIT(x)",
+        "GENMASK(x, 2)", "GENMASK(7, x)", "FIELD_GET(0x70, x)",
+        "FIELD_GET(x, 0x70)", "FIELD_PREP(0x70, x)", "FIELD_PREP(x, 5)",
+        "setbit(x, 3)", "clearbit(x, 0)", "togglebit(x, x)", "testbit(x, 1)",
+        "extract(x, 6, 2)", "insert(x, 5, 4, 2)", "insert(0, x, 7, x)",
+    ];
+
+    #[test]
+    fn matches_evaluator() {
+        let inputs: Vec<u128> = vec![
+            0, 1, 2, 3, 5, 7, 8, 100, 0x7f, 0x80, 0xff, 0x1234,
+            0x8000_0000, u128::MAX >> 1, 1 << 127, u128::MAX,
+        ];
+        for &width in &[Width::W8, Width::W32, Width::W128] {
+            for &signed in &[false, true] {
+                let evaluator = evaluator(width, signed);
+                for line in EXPRESSIONS {
+                    let expr = expression(line, width);
+                    let mut program = evaluator.compile(&expr, "x").unwrap();
+                    for &input in &inputs {
+                        let x = width.mask(BigInt::from(input));
+                        let locals = vec![("x".to_string(), x)].into_iter().collect();
+                        let expected = evaluator
+                            .apply(&expr, &locals)
+                            .map(|value| value.to_u128().unwrap());
+                        assert_eq!(
+                            program.run(input),
+                            expected,
+                            "{} with x = {:#x} in {} {}",
+                            line,
+                            input,
+                            if signed { "signed" } else { "unsigned" },
+                            width
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compile_fallback() {
+        let mut evaluator = evaluator(Width::W32, false);
+        evaluator.evaluate(statement("def twice(v) = v * 2", Width::W32)).unwrap();
+        evaluator.evaluate(statement("mask = 0xf0", Width::W32)).unwrap();
+
+        let compile = |line| evaluator.compile(&expression(line, Width::W32), "x");
+        let mut program = compile("x & mask").unwrap();
+        assert_eq!(program.run(0xabc), Ok(0xb0));
+        assert!(compile("twice(x)").is_none());
+        assert!(compile("x & y").is_none());
+        assert!(compile("popcount(x, 1)").is_none());
+        assert!(compile("a = x").is_none());
+
+        evaluator.set_width(Width::Unbounded);
+        assert!(evaluator.compile(&expression("x + 1", Width::W32), "x").is_none());
+    }
+
+    #[test]
+    fn short_circuit() {
+        let evaluator = evaluator(Width::W32, false);
+        let compile = |line| evaluator.compile(&expression(line, Width::W32), "x").unwrap();
+        let mut program = compile("x && 1 / x");
+        assert_eq!(program.run(0), Ok(0));
+        assert_eq!(program.run(1), Ok(1));
+        let mut program = compile("(x || 1 / x) + 1");
+        assert_eq!(program.run(0), Err(EvaluatorError::DivisionByZero(Span::new(8, 9))));
+        assert_eq!(program.run(3), Ok(2));
+    }
+
+    // A benchmark rather than a test, run it with
+    // cargo test --release -- --ignored --nocapture throughput
+    #[test]
+    #[ignore]
+    fn throughput() {
+        let evaluator = evaluator(Width::W64, false);
+        let expr = expression("((x >> 4) & 0xf) | (popcount(x) << 8) ^ (x <<< 7)", Width::W64);
+        let mut program = evaluator.compile(&expr, "x").unwrap();
+        let input = |index: u64| u128::from(index.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+
+        let count = 10_000_000;
+        let start = Instant::now();
+        for index in 0..count {
+            black_box(program.run(input(index)).unwrap());
+        }
+        let compiled = count as f64 / start.elapsed().as_secs_f64();
+
+        let count = count / 10;
+        let start = Instant::now();
+        for index in 0..count {
+            let locals = vec![("x".to_string(), BigInt::from(input(index)))].into_iter().collect();
+            black_box(evaluator.apply(&expr, &locals).unwrap());
+        }
+        let evaluated = count as f64 / start.elapsed().as_secs_f64();
+
+        println!(
+            "compiled: {:.1}M inputs/s, evaluated: {:.1}M inputs/s ({:.0}x faster)",
+            compiled / 1e6,
+            evaluated / 1e6,
+            compiled / evaluated
+        );
+    }
+}
@@ -0,0 +1,61 @@
+// A statement of a script along with where it starts, so errors can be
+// reported as file:line:column
+#[derive(Debug, PartialEq)]
+pub struct Statement {
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+// Split a script into statements. Each line holds statements separated by `;`,
+// and everything after a `#` is a comment, which also covers a shebang line.
+pub fn statements(source: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut column = 0;
+        for text in code.split(';') {
+            if !text.trim().is_empty() {
+                statements.push(Statement {
+                    line: number + 1,
+                    column,
+                    text: text.to_string(),
+                });
+            }
+            column += text.chars().count() + 1;
+        }
+    }
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use script::{statements, Statement};
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let source = "#!/usr/bin/env bspl\n\n# a mask\nmask = 0xff << 8  # high byte\n";
+        assert_eq!(
+            statements(source),
+            vec![Statement {
+                line: 4,
+                column: 0,
+                text: "mask = 0xff << 8  ".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn separators() {
+        let found = statements("x = 1; y = 2;; x | y");
+        let texts: Vec<(usize, &str)> = found
+            .iter()
+            .map(|statement| (statement.column, statement.text.as_str()))
+            .collect();
+        assert_eq!(texts, vec![(0, "x = 1"), (6, " y = 2"), (14, " x | y")]);
+        assert!(found.iter().all(|statement| statement.line == 1));
+    }
+}